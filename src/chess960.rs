@@ -0,0 +1,429 @@
+use chess::{
+    between, get_bishop_moves, get_king_moves, get_knight_moves, get_pawn_attacks, get_rook_moves,
+    BitBoard, Board, BoardBuilder, ChessMove, Color, File, Piece, Square, EMPTY,
+};
+use std::str::FromStr;
+
+// the chess crate can only castle with the king on the e-file and the rooks in the corners. in
+// chess960 they can start anywhere on the back rank, so the board is kept without any castling
+// rights and the rooks each side can still castle with are tracked here instead. castling is
+// generated and made here too, encoded as the king taking its own rook the way uci sends it,
+// which can't be confused with any other move
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct CastlingRooks {
+    rooks: [[Option<Square>; 2]; 2], // [colour][kingside, queenside]
+}
+
+const KINGSIDE: usize = 0;
+const QUEENSIDE: usize = 1;
+
+// mixed into the board's hash, so positions that only differ in castling rights don't share
+// transposition table entries or count as repetitions of each other
+const CASTLING_KEYS: [[u64; 2]; 2] = [
+    [0x9d39_247e_3377_6d41, 0x2af7_3980_05aa_a5c7],
+    [0x44db_0150_2462_3547, 0x9c15_f73e_62a7_6ae2],
+];
+
+impl CastlingRooks {
+    pub fn hash(&self) -> u64 {
+        let mut hash = 0;
+
+        for (colour, rooks) in self.rooks.iter().enumerate() {
+            for (side, rook) in rooks.iter().enumerate() {
+                if rook.is_some() {
+                    hash ^= CASTLING_KEYS[colour][side];
+                }
+            }
+        }
+
+        hash
+    }
+
+    // the rights left after a move: a king move loses both of its side's, and a move from or to
+    // a rook's square means that rook has moved or been captured
+    pub fn after(&self, board: &Board, mov: ChessMove) -> Self {
+        if *self == Self::default() {
+            return *self;
+        }
+
+        let mut rooks = self.rooks;
+
+        if board.piece_on(mov.get_source()) == Some(Piece::King) {
+            rooks[board.side_to_move().to_index()] = [None; 2];
+        }
+
+        for rook in rooks.iter_mut().flatten() {
+            if *rook == Some(mov.get_source()) || *rook == Some(mov.get_dest()) {
+                *rook = None;
+            }
+        }
+
+        Self { rooks }
+    }
+
+    // the castling field of a fen in the shredder style, the file of each rook that can castle
+    pub fn to_fen(self) -> String {
+        let mut field = String::new();
+
+        for (colour, rooks) in self.rooks.iter().enumerate() {
+            for rook in rooks.iter().flatten() {
+                let file = (b'A' + rook.get_file().to_index() as u8) as char;
+
+                field.push(match colour {
+                    0 => file,
+                    _ => file.to_ascii_lowercase(),
+                });
+            }
+        }
+
+        if field.is_empty() {
+            field.push('-');
+        }
+
+        field
+    }
+}
+
+// reads the castling field as either shredder-fen, with the file of each rook that can castle,
+// or x-fen, where KQkq mean the outermost rook on that side of the king and a file is only
+// given when it's some other rook
+pub fn load_board(fen: &str) -> Result<(Board, CastlingRooks), chess::Error> {
+    let invalid = || chess::Error::InvalidFen {
+        fen: fen.to_string(),
+    };
+
+    let mut fields = fen.split_whitespace().collect::<Vec<_>>();
+
+    if fields.len() < 4 {
+        return Err(invalid());
+    }
+
+    let castling = fields[2];
+
+    fields[2] = "-";
+
+    let board = Board::from_str(&fields.join(" "))?;
+
+    let mut castling_rooks = CastlingRooks::default();
+
+    for right in castling.chars().filter(|&right| right != '-') {
+        let colour = if right.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+
+        let back_rank = colour.to_my_backrank();
+
+        let king = board.king_square(colour);
+
+        if king.get_rank() != back_rank {
+            return Err(invalid());
+        }
+
+        let rooks = board.pieces(Piece::Rook) & board.color_combined(colour);
+
+        let rook_on = |file: usize| {
+            let square = Square::make_square(back_rank, File::from_index(file));
+
+            (rooks & BitBoard::from_square(square) != EMPTY).then_some(square)
+        };
+
+        let king_file = king.get_file().to_index();
+
+        let rook = match right.to_ascii_uppercase() {
+            'K' => (king_file + 1..8).rev().find_map(rook_on),
+            'Q' => (0..king_file).find_map(rook_on),
+            file @ 'A'..='H' => rook_on((file as u8 - b'A') as usize),
+            _ => None,
+        }
+        .ok_or_else(invalid)?;
+
+        let side = if rook.get_file().to_index() > king_file {
+            KINGSIDE
+        } else {
+            QUEENSIDE
+        };
+
+        castling_rooks.rooks[colour.to_index()][side] = Some(rook);
+    }
+
+    Ok((board, castling_rooks))
+}
+
+// the legal castling moves, as the king taking the rook it castles with
+pub fn castling_moves(board: &Board, castling_rooks: &CastlingRooks) -> Vec<ChessMove> {
+    let colour = board.side_to_move();
+
+    let rooks = castling_rooks.rooks[colour.to_index()];
+
+    if rooks == [None; 2] || *board.checkers() != EMPTY {
+        return Vec::new();
+    }
+
+    let king = board.king_square(colour);
+
+    rooks
+        .into_iter()
+        .flatten()
+        .map(|rook| ChessMove::new(king, rook, None))
+        .filter(|&mov| can_castle(board, mov))
+        .collect()
+}
+
+fn can_castle(board: &Board, mov: ChessMove) -> bool {
+    let colour = board.side_to_move();
+
+    let castle = match castling(board, mov) {
+        Some(castle) => castle,
+        None => return false,
+    };
+
+    let (king, king_dest) = castle.king;
+    let (rook, rook_dest) = castle.rook;
+
+    // every square either piece crosses or lands on has to be empty, apart from the two of them
+    let king_path = between(king, king_dest) | BitBoard::from_square(king_dest);
+    let rook_path = between(rook, rook_dest) | BitBoard::from_square(rook_dest);
+
+    let others = board.combined() ^ BitBoard::from_square(king) ^ BitBoard::from_square(rook);
+
+    if (king_path | rook_path) & others != EMPTY {
+        return false;
+    }
+
+    // the king can't pass through check, and can't end up in it either, which is looked at with
+    // the pieces where they are after castling since the rook moving away can uncover an attack
+    if between(king, king_dest).any(|square| is_attacked(board, square, !colour, *board.combined()))
+    {
+        return false;
+    }
+
+    let occupied_after =
+        others | BitBoard::from_square(king_dest) | BitBoard::from_square(rook_dest);
+
+    !is_attacked(board, king_dest, !colour, occupied_after)
+}
+
+pub struct Castle {
+    pub king: (Square, Square), // source and destination
+    pub rook: (Square, Square),
+}
+
+// the squares the king and rook move between if the move castles, however it's encoded: the king
+// moving two files when the chess crate castles, or the king taking its own rook in chess960
+pub fn castling(board: &Board, mov: ChessMove) -> Option<Castle> {
+    let source = mov.get_source();
+    let dest = mov.get_dest();
+
+    if board.piece_on(source) != Some(Piece::King) {
+        return None;
+    }
+
+    let back_rank = source.get_rank();
+
+    let square = |file| Square::make_square(back_rank, file);
+
+    let kingside = dest.get_file().to_index() > source.get_file().to_index();
+
+    let (king_dest, rook_dest) = if kingside {
+        (square(File::G), square(File::F))
+    } else {
+        (square(File::C), square(File::D))
+    };
+
+    if board.color_on(dest) == Some(board.side_to_move()) {
+        return Some(Castle {
+            king: (source, king_dest),
+            rook: (dest, rook_dest),
+        });
+    }
+
+    // castling is the only king move of two files
+    if source
+        .get_file()
+        .to_index()
+        .abs_diff(dest.get_file().to_index())
+        == 2
+    {
+        let rook = if kingside { File::H } else { File::A };
+
+        return Some(Castle {
+            king: (source, dest),
+            rook: (square(rook), rook_dest),
+        });
+    }
+
+    None
+}
+
+// makes any move, including a chess960 castle, which the chess crate can't make itself
+pub fn make_move(board: &Board, mov: ChessMove) -> Board {
+    if board.color_on(mov.get_dest()) != Some(board.side_to_move()) {
+        return board.make_move_new(mov);
+    }
+
+    match castling(board, mov) {
+        Some(castle) => make_castle(board, castle),
+        None => board.make_move_new(mov),
+    }
+}
+
+fn make_castle(board: &Board, castle: Castle) -> Board {
+    let colour = board.side_to_move();
+
+    let mut builder = BoardBuilder::from(board);
+
+    builder
+        .clear_square(castle.king.0)
+        .clear_square(castle.rook.0)
+        .piece(castle.king.1, Piece::King, colour)
+        .piece(castle.rook.1, Piece::Rook, colour)
+        .side_to_move(!colour)
+        .en_passant(None);
+
+    Board::try_from(&builder).unwrap()
+}
+
+// legal on the crate's board, or as a chess960 castle
+pub fn is_legal(board: &Board, castling_rooks: &CastlingRooks, mov: ChessMove) -> bool {
+    board.legal(mov) || castling_moves(board, castling_rooks).contains(&mov)
+}
+
+fn is_attacked(board: &Board, square: Square, by: Color, occupied: BitBoard) -> bool {
+    let theirs = *board.color_combined(by);
+
+    let bishops = (board.pieces(Piece::Bishop) | board.pieces(Piece::Queen)) & theirs;
+    let rooks = (board.pieces(Piece::Rook) | board.pieces(Piece::Queen)) & theirs;
+
+    let attackers = (get_pawn_attacks(square, !by, *board.pieces(Piece::Pawn) & theirs))
+        | (get_knight_moves(square) & board.pieces(Piece::Knight) & theirs)
+        | (get_king_moves(square) & board.pieces(Piece::King) & theirs)
+        | (get_bishop_moves(square, occupied) & bishops)
+        | (get_rook_moves(square, occupied) & rooks);
+
+    attackers != EMPTY
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::perft::perft;
+
+    fn mov(uci: &str) -> ChessMove {
+        ChessMove::from_str(uci).unwrap()
+    }
+
+    #[test]
+    fn reads_shredder_and_x_fen_castling() {
+        let shredder = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+
+        let (_, castling_rooks) = load_board(shredder).unwrap();
+
+        assert_eq!(castling_rooks.to_fen(), "HFhf");
+
+        // KQkq are the outermost rooks, so the same rights can be written either way
+        let x_fen = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KFkf - 2 9";
+
+        assert_eq!(load_board(x_fen).unwrap().1, castling_rooks);
+
+        let (_, castling_rooks) = load_board("rk2r3/8/8/8/8/8/8/RK2R3 w KQq - 0 1").unwrap();
+
+        assert_eq!(castling_rooks.to_fen(), "EAa");
+
+        let (_, castling_rooks) = load_board("rk2r3/8/8/8/8/8/8/RK2R3 w - - 0 1").unwrap();
+
+        assert_eq!(castling_rooks, CastlingRooks::default());
+
+        // a right for a rook that isn't there
+        assert!(load_board("rk2r3/8/8/8/8/8/8/RK2R3 w H - 0 1").is_err());
+    }
+
+    #[test]
+    fn plays_castling_from_a_960_start() {
+        let (board, castling_rooks) = load_board("rk2r3/8/8/8/8/8/8/RK2R3 w AEae - 0 1").unwrap();
+
+        // the black rook on e8 covers e1, which the king would cross castling kingside
+        assert_eq!(castling_moves(&board, &castling_rooks), vec![mov("b1a1")]);
+        assert!(!board.legal(mov("b1a1")));
+
+        let castle = castling(&board, mov("b1a1")).unwrap();
+
+        assert_eq!(castle.king, (Square::B1, Square::C1));
+        assert_eq!(castle.rook, (Square::A1, Square::D1));
+
+        let castling_rooks = castling_rooks.after(&board, mov("b1a1"));
+        let board = make_move(&board, mov("b1a1"));
+
+        assert_eq!(board.piece_on(Square::C1), Some(Piece::King));
+        assert_eq!(board.piece_on(Square::D1), Some(Piece::Rook));
+        assert_eq!(board.piece_on(Square::A1), None);
+        assert_eq!(board.piece_on(Square::B1), None);
+        assert_eq!(board.side_to_move(), Color::Black);
+        assert_eq!(castling_rooks.to_fen(), "ea");
+
+        // black castles the same way in reply, which the rook now on d1 stops on the kingside
+        assert!(is_legal(&board, &castling_rooks, mov("b8a8")));
+        assert!(!is_legal(&board, &castling_rooks, mov("b8e8")));
+
+        let castling_rooks = castling_rooks.after(&board, mov("b8a8"));
+        let board = make_move(&board, mov("b8a8"));
+
+        assert_eq!(board.piece_on(Square::C8), Some(Piece::King));
+        assert_eq!(board.piece_on(Square::D8), Some(Piece::Rook));
+        assert_eq!(castling_rooks, CastlingRooks::default());
+    }
+
+    #[test]
+    fn castling_with_the_king_or_rook_already_in_place() {
+        // the king starts on g1, so castling kingside only moves the rook
+        let (board, castling_rooks) = load_board("4k3/8/8/8/8/8/8/3R2KR w H - 0 1").unwrap();
+
+        assert_eq!(castling_moves(&board, &castling_rooks), vec![mov("g1h1")]);
+
+        let board = make_move(&board, mov("g1h1"));
+
+        assert_eq!(board.piece_on(Square::G1), Some(Piece::King));
+        assert_eq!(board.piece_on(Square::F1), Some(Piece::Rook));
+        assert_eq!(board.piece_on(Square::H1), None);
+
+        // the rook moving away from b1 would uncover the queen's attack on the king's destination
+        let (board, castling_rooks) = load_board("4k3/8/8/8/8/8/8/qRK5 w B - 0 1").unwrap();
+
+        assert!(castling_moves(&board, &castling_rooks).is_empty());
+    }
+
+    #[test]
+    fn perft_counts_match_known_960_results() {
+        let positions = [
+            (
+                "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+                [21, 528, 12189],
+            ),
+            (
+                "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+                [21, 807, 18002],
+            ),
+            (
+                "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+                [20, 479, 10471],
+            ),
+            (
+                "qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9",
+                [22, 593, 13440],
+            ),
+        ];
+
+        for (fen, counts) in positions {
+            let (board, castling_rooks) = load_board(fen).unwrap();
+
+            for (depth, count) in (1..).zip(counts) {
+                assert_eq!(
+                    perft(&board, &castling_rooks, depth),
+                    count,
+                    "{fen} depth {depth}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use chess::{Board, ChessMove};
+use std::fs;
+use std::str::FromStr;
+use std::time::Duration;
+
+pub const DEFAULT_EPD_MOVE_TIME: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EpdLimit {
+    Depth(u8),
+    MoveTime(Duration),
+}
+
+// a test suite being worked through, one search at a time
+pub struct EpdRun {
+    pub positions: Vec<EpdPosition>,
+    pub limit: EpdLimit, // how long each position is searched for
+    pub current: usize,  // position being searched
+    pub solved: usize,   // positions solved so far
+    pub stopped: bool,   // a stop cut the current search short, so the run ends with it
+}
+
+pub struct EpdPosition {
+    pub id: String,   // the id operation, or the line number if there isn't one
+    pub board: Board, // position to search
+    pub best_moves: Vec<ChessMove>, // bm, any of which solves the position
+    pub avoid_moves: Vec<ChessMove>, // am, none of which may be played
+}
+
+impl EpdPosition {
+    pub fn solved_by(&self, mov: ChessMove) -> bool {
+        (self.best_moves.is_empty() || self.best_moves.contains(&mov))
+            && !self.avoid_moves.contains(&mov)
+    }
+}
+
+// each line is the first four fields of a fen followed by operations separated by semicolons,
+// such as
+//   r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - bm Bb5; id "ruy lopez";
+// only bm, am and id are used, the moves are in san. lines without bm or am have nothing to
+// check and are skipped
+pub fn load_epd(path: &str) -> Result<Vec<EpdPosition>, String> {
+    let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+
+    let mut positions = Vec::new();
+
+    for (line_number, line) in contents.lines().enumerate() {
+        let line_number = line_number + 1;
+
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields = line.splitn(5, ' ').collect::<Vec<_>>();
+
+        if fields.len() < 5 {
+            return Err(format!(
+                "line {line_number}: expected a position and operations"
+            ));
+        }
+
+        // epd has no move counters, which the fen parser wants
+        let fen = format!("{} 0 1", fields[..4].join(" "));
+
+        let board = Board::from_str(&fen)
+            .map_err(|err| format!("line {line_number}: invalid position: {err}"))?;
+
+        let mut position = EpdPosition {
+            id: line_number.to_string(),
+            board,
+            best_moves: Vec::new(),
+            avoid_moves: Vec::new(),
+        };
+
+        for operation in fields[4].split(';') {
+            let operation = operation.trim();
+
+            let (opcode, operands) = operation.split_once(' ').unwrap_or((operation, ""));
+
+            let moves = || {
+                operands
+                    .split_whitespace()
+                    .map(|san| {
+                        parse_san(&board, san)
+                            .ok_or_else(|| format!("line {line_number}: invalid move {san}"))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            };
+
+            match opcode {
+                "bm" => position.best_moves = moves()?,
+                "am" => position.avoid_moves = moves()?,
+                "id" => position.id = operands.trim().trim_matches('"').to_string(),
+                _ => (),
+            }
+        }
+
+        if !position.best_moves.is_empty() || !position.avoid_moves.is_empty() {
+            positions.push(position);
+        }
+    }
+
+    if positions.is_empty() {
+        return Err("no positions with a bm or am operation".to_string());
+    }
+
+    Ok(positions)
+}
+
+// the chess crate's san parser doesn't take check marks on castling, annotations, castling with
+// zeros or an equals sign before a promotion, all of which turn up in test suites
+fn parse_san(board: &Board, san: &str) -> Option<ChessMove> {
+    let san = san
+        .trim_end_matches(['+', '#', '!', '?'])
+        .replace('0', "O")
+        .replace('=', "");
+
+    ChessMove::from_san(board, &san).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::{Piece, Square};
+
+    // load_epd reads from a file, so each test writes its own, named so tests running at the
+    // same time don't share one
+    fn load(name: &str, contents: &str) -> Result<Vec<EpdPosition>, String> {
+        let path = std::env::temp_dir().join(format!("kittycat-{}-{name}.epd", std::process::id()));
+
+        fs::write(&path, contents).unwrap();
+
+        let positions = load_epd(path.to_str().unwrap());
+
+        fs::remove_file(&path).unwrap();
+
+        positions
+    }
+
+    #[test]
+    fn reads_bm_am_and_id() {
+        let positions = load(
+            "operations",
+            "# a comment\n\
+             r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - bm Bb5 Bc4; id \"open\";\n\
+             rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - id \"nothing to check\";\n\
+             rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - am g4;\n",
+        )
+        .unwrap();
+
+        assert_eq!(positions.len(), 2);
+
+        assert_eq!(positions[0].id, "open");
+        assert_eq!(
+            positions[0].best_moves,
+            vec![
+                ChessMove::new(Square::F1, Square::B5, None),
+                ChessMove::new(Square::F1, Square::C4, None),
+            ]
+        );
+        assert!(positions[0].avoid_moves.is_empty());
+
+        // without an id the line number is used
+        assert_eq!(positions[1].id, "4");
+        assert_eq!(
+            positions[1].avoid_moves,
+            vec![ChessMove::new(Square::G2, Square::G4, None)]
+        );
+
+        assert!(positions[0].solved_by(ChessMove::new(Square::F1, Square::C4, None)));
+        assert!(!positions[0].solved_by(ChessMove::new(Square::D2, Square::D4, None)));
+        assert!(!positions[1].solved_by(ChessMove::new(Square::G2, Square::G4, None)));
+        assert!(positions[1].solved_by(ChessMove::new(Square::E2, Square::E4, None)));
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        assert!(load("no-operations", "8/8/8/8/8/8/8/K6k w - -\n").is_err());
+        assert!(load("bad-move", "4k3/8/8/8/8/8/8/4K3 w - - bm Qd8;\n").is_err());
+        assert!(load("nothing-to-check", "4k3/8/8/8/8/8/8/4K3 w - - id \"a\";\n").is_err());
+    }
+
+    #[test]
+    fn parses_castling_with_zeros_and_check_marks() {
+        let board = Board::from_str("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1").unwrap();
+
+        let kingside = ChessMove::new(Square::E1, Square::G1, None);
+        let queenside = ChessMove::new(Square::E1, Square::C1, None);
+
+        assert_eq!(parse_san(&board, "O-O"), Some(kingside));
+        assert_eq!(parse_san(&board, "0-0"), Some(kingside));
+        assert_eq!(parse_san(&board, "0-0+"), Some(kingside));
+        assert_eq!(parse_san(&board, "0-0-0"), Some(queenside));
+        assert_eq!(parse_san(&board, "O-O-O!?"), Some(queenside));
+    }
+
+    #[test]
+    fn parses_promotions_with_an_equals_sign() {
+        let board = Board::from_str("8/4P1k1/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+
+        let queen = ChessMove::new(Square::E7, Square::E8, Some(Piece::Queen));
+        let knight = ChessMove::new(Square::E7, Square::E8, Some(Piece::Knight));
+
+        assert_eq!(parse_san(&board, "e8=Q"), Some(queen));
+        assert_eq!(parse_san(&board, "e8Q"), Some(queen));
+        assert_eq!(parse_san(&board, "e8=N+"), Some(knight));
+    }
+}
@@ -1,59 +1,1363 @@
-use chess::{Board, Color, Piece, Square};
+use crate::chess960::castling;
+use crate::INFINITY;
+use chess::{
+    get_bishop_moves, get_king_moves, get_knight_moves, get_rook_moves, BitBoard, Board, ChessMove,
+    Color, Piece, Square, ALL_PIECES, ALL_SQUARES, EMPTY,
+};
+use std::fmt;
+use std::fs;
+use std::ops::{Add, AddAssign, Mul, Neg};
+use std::sync::OnceLock;
 
-pub fn evaluate_position(board: &Board) -> i32 {
-    let mut score = 0;
+// keep static evals well clear of the mate threshold (INFINITY / 2) used when reporting scores
+pub const MAX_EVAL: i32 = INFINITY / 2 - 1000;
 
-    let is_endgame = is_endgame(board);
+pub const MAX_PHASE: i32 = 24;
+const KNIGHT_PHASE: i32 = 1;
+const BISHOP_PHASE: i32 = 1;
+const ROOK_PHASE: i32 = 2;
+const QUEEN_PHASE: i32 = 4;
+
+const PAWN_HASH_ENTRIES: usize = 1 << 14; // power of two so the index is a mask
+
+pub const LIGHT_SQUARES: BitBoard = BitBoard(0x55aa_55aa_55aa_55aa);
+
+const OPPOSITE_BISHOPS_SCALE_PERCENT: i32 = 50; // share of the eval kept in such endings
+const FIFTY_MOVE_PLIES: u16 = 100; // halfmove clock at which the game is drawn
+
+const TEMPO_BONUS: Score = Score { mg: 12, eg: 0 };
+
+const DOUBLED_PAWN_PENALTY: Score = Score { mg: 10, eg: 15 }; // per extra pawn on a file
+const ISOLATED_PAWN_PENALTY: Score = Score { mg: 10, eg: 15 };
+const ISOLATED_HALF_OPEN_PAWN_PENALTY: Score = Score { mg: 20, eg: 20 }; // no enemy pawn in front
+const BACKWARD_PAWN_PENALTY: Score = Score { mg: 8, eg: 10 };
+const BACKWARD_HALF_OPEN_PAWN_PENALTY: Score = Score { mg: 16, eg: 12 }; // no enemy pawn in front
+
+const ROOK_OPEN_FILE_BONUS: Score = Score { mg: 25, eg: 10 };
+const ROOK_SEMI_OPEN_FILE_BONUS: Score = Score { mg: 12, eg: 6 }; // only enemy pawns on the file
+const ROOK_ISOLATED_PAWN_BONUS: Score = Score { mg: 8, eg: 8 }; // semi-open onto an isolated pawn
+const ROOK_ON_SEVENTH_BONUS: Score = Score { mg: 20, eg: 30 };
+const ROOK_ON_SEVENTH_KING_BONUS: Score = Score { mg: 10, eg: 20 }; // king stuck on the 8th
+const ROOKS_DOUBLED_ON_SEVENTH_BONUS: Score = Score { mg: 15, eg: 25 };
+
+const KNIGHT_OUTPOST_BONUS: Score = Score { mg: 20, eg: 15 };
+const KNIGHT_CENTRAL_OUTPOST_BONUS: Score = Score { mg: 10, eg: 5 }; // extra on c to f files
+
+const TRAPPED_BISHOP_PENALTY: Score = Score { mg: 120, eg: 100 };
+const BAD_BISHOP_PAWN_PENALTY: Score = Score { mg: 3, eg: 6 }; // per own pawn on its colour
+
+// a bishop that takes a pawn in the corner can be shut in by a pawn push and is usually lost
+// for a couple of pawns. pairs of the bishop's square and the enemy pawn that traps it, for a
+// white bishop, with the ranks flipped for black
+const TRAPPED_BISHOP_SQUARES: [(Square, Square); 4] = [
+    (Square::A7, Square::B6),
+    (Square::H7, Square::G6),
+    (Square::A6, Square::B5),
+    (Square::H6, Square::G5),
+];
+
+const IMBALANCE_BASE_PAWNS: i32 = 5; // own pawns at which knights and rooks are worth their value
+const KNIGHT_PAWN_ADJUSTMENT: Score = Score { mg: 6, eg: 6 }; // per own pawn above the base
+const ROOK_PAWN_ADJUSTMENT: Score = Score { mg: -12, eg: -12 }; // per own pawn above the base
+const BISHOP_PAIR_BONUS: Score = Score { mg: 40, eg: 60 };
+const BISHOP_PAIR_PAWN_ADJUSTMENT: Score = Score { mg: -1, eg: -1 }; // per pawn of either side
+const BISHOP_PAIR_NO_ENEMY_MINORS_BONUS: Score = Score { mg: 10, eg: 15 };
+
+pub fn evaluate_position(
+    board: &Board,
+    psqt: Score,
+    halfmove_clock: u16,
+    pawn_table: &mut PawnHashTable,
+) -> i32 {
+    let white = evaluate_white(board, psqt, pawn_table);
+
+    side_to_move_score(board, white, halfmove_clock)
+}
+
+// turns the eval for white into the score for the side to move, adjusted for who has the move
+// and for how likely the advantage is to be converted
+fn side_to_move_score(board: &Board, white: i32, halfmove_clock: u16) -> i32 {
+    // having the move is worth a little, mostly while there are still pieces to develop
+    let tempo = TEMPO_BONUS.taper(game_phase(board));
+
+    let score = match board.side_to_move() {
+        Color::White => white + tempo,
+        Color::Black => white - tempo,
+    };
+
+    // with only pawns besides the bishops, each bishop can blockade pawns on the squares the
+    // other can never contest, so being a pawn or two up is often still a draw
+    let score = if is_opposite_coloured_bishop_ending(board) {
+        score * OPPOSITE_BISHOPS_SCALE_PERCENT / 100
+    } else {
+        score
+    };
+
+    // an advantage that hasn't led to a pawn move or capture for a long time is less and less
+    // likely to be converted before the fifty-move rule kicks in. static evals never reach the
+    // mate range, so mate scores from the search are left alone
+    let score = score * (FIFTY_MOVE_PLIES - halfmove_clock.min(FIFTY_MOVE_PLIES)) as i32
+        / FIFTY_MOVE_PLIES as i32;
+
+    match board.side_to_move() {
+        Color::White => score,
+        Color::Black => -score,
+    }
+}
+
+fn is_opposite_coloured_bishop_ending(board: &Board) -> bool {
+    let bishops = *board.pieces(Piece::Bishop);
+
+    let white_bishops = bishops & board.color_combined(Color::White);
+    let black_bishops = bishops & board.color_combined(Color::Black);
+
+    let others =
+        *board.pieces(Piece::Knight) | board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+
+    others == EMPTY
+        && white_bishops.popcnt() == 1
+        && black_bishops.popcnt() == 1
+        && (bishops & LIGHT_SQUARES).popcnt() == 1
+}
+
+// psqt is the material and piece-square score, which the search keeps up to date move by move
+// rather than summing over the whole board at every leaf
+pub fn evaluate_white(board: &Board, psqt: Score, pawn_table: &mut PawnHashTable) -> i32 {
+    let mut score = psqt + pawn_structure(board, pawn_table);
+
+    let white_terms = positional_terms(board, Color::White);
+    let black_terms = positional_terms(board, Color::Black);
+
+    for (white, black) in white_terms.into_iter().zip(black_terms) {
+        score += white + -black;
+    }
+
+    let score = score.taper(game_phase(board)) + kbnk_mate(board);
+
+    score.clamp(-MAX_EVAL, MAX_EVAL)
+}
+
+const POSITIONAL_TERM_NAMES: [&str; 9] = [
+    "Mobility",
+    "King safety",
+    "Pawn shield",
+    "Rook files",
+    "Rooks on 7th",
+    "Knight outposts",
+    "Trapped bishops",
+    "Bad bishops",
+    "Imbalance",
+];
+
+// every term besides material, piece-square and pawn structure, for one side and in the order
+// of POSITIONAL_TERM_NAMES
+fn positional_terms(board: &Board, colour: Color) -> [Score; 9] {
+    [
+        mobility(board, colour),
+        king_safety(board, colour),
+        pawn_shield(board, colour),
+        rook_files(board, colour),
+        rooks_on_seventh(board, colour),
+        knight_outposts(board, colour),
+        trapped_bishops(board, colour),
+        bad_bishops(board, colour),
+        imbalance(board, colour),
+    ]
+}
+
+// the evaluation term by term for each side, for the eval command. the total is worked out the
+// same way as evaluate_position, so it's exactly what the search sees
+pub fn evaluate_verbose(
+    board: &Board,
+    weights: &EvalWeights,
+    halfmove_clock: u16,
+) -> EvalBreakdown {
+    let side_term = |name, term: &dyn Fn(Color) -> Score| EvalTerm {
+        name,
+        white: term(Color::White),
+        black: term(Color::Black),
+    };
+
+    let material = side_term("Material", &|colour| material(board, colour, weights));
+
+    let piece_square = side_term("Piece-square", &|colour| {
+        piece_square(board, colour, weights)
+    });
+
+    let pawns = side_term("Pawn structure", &|colour| {
+        pawn_structure_side(board, colour)
+    });
+
+    let white_terms = positional_terms(board, Color::White);
+    let black_terms = positional_terms(board, Color::Black);
+
+    let positional = POSITIONAL_TERM_NAMES
+        .iter()
+        .zip(white_terms.into_iter().zip(black_terms))
+        .map(|(&name, (white, black))| EvalTerm { name, white, black });
+
+    let terms: Vec<EvalTerm> = [material, piece_square, pawns]
+        .into_iter()
+        .chain(positional)
+        .collect();
+
+    let phase = game_phase(board);
+
+    let sum = terms
+        .iter()
+        .fold(Score::default(), |sum, term| sum + term.white + -term.black);
+
+    let kbnk = kbnk_mate(board);
+
+    let white = (sum.taper(phase) + kbnk).clamp(-MAX_EVAL, MAX_EVAL);
+
+    debug_assert_eq!(
+        white,
+        evaluate_white(
+            board,
+            material_and_psqt(board, weights),
+            &mut PawnHashTable::new()
+        )
+    );
+
+    let side_to_move = side_to_move_score(board, white, halfmove_clock);
+
+    EvalBreakdown {
+        terms,
+        phase,
+        kbnk,
+        white: white_score(board, side_to_move),
+        side_to_move,
+    }
+}
+
+// an eval for the side to move seen from white's side, so the two only ever differ in sign
+pub fn white_score(board: &Board, side_to_move: i32) -> i32 {
+    match board.side_to_move() {
+        Color::White => side_to_move,
+        Color::Black => -side_to_move,
+    }
+}
+
+// one side's material, positive for either colour
+fn material(board: &Board, colour: Color, weights: &EvalWeights) -> Score {
+    let mut score = Score::default();
+
+    for piece in ALL_PIECES {
+        let count = (board.pieces(piece) & board.color_combined(colour)).popcnt() as i32;
+
+        score += weights.piece_values[piece.to_index()] * count;
+    }
+
+    score
+}
+
+// one side's share of the incremental score that isn't material, positive for either colour
+fn piece_square(board: &Board, colour: Color, weights: &EvalWeights) -> Score {
+    let mut score = Score::default();
+
+    for square in *board.color_combined(colour) {
+        let piece = board.piece_on(square).unwrap(); // the square is occupied
+
+        score += piece_score(piece, colour, square, weights);
+    }
+
+    let score = match colour {
+        Color::White => score,
+        Color::Black => -score,
+    };
+
+    score + -material(board, colour, weights)
+}
+
+struct EvalTerm {
+    name: &'static str,
+    white: Score,
+    black: Score,
+}
+
+pub struct EvalBreakdown {
+    terms: Vec<EvalTerm>,
+    phase: i32,            // game phase the terms are tapered by
+    kbnk: i32,             // mating bonus with king, bishop and knight against a lone king
+    pub white: i32,        // the eval the search uses, from white's side
+    pub side_to_move: i32, // the eval the search uses
+}
+
+// a table of every term's midgame and endgame score for each side and the difference between
+// them, then the tapered total
+impl fmt::Display for EvalBreakdown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<16}|{:^15}|{:^15}|{:^15}",
+            "Term", "White", "Black", "Total"
+        )?;
+        writeln!(
+            f,
+            "{:<16}|{:>7}{:>7} |{:>7}{:>7} |{:>7}{:>7}",
+            "", "MG", "EG", "MG", "EG", "MG", "EG"
+        )?;
+        writeln!(f, "{:-<16}+{:-<15}+{:-<15}+{:-<15}", "", "", "", "")?;
+
+        for term in &self.terms {
+            let total = term.white + -term.black;
+
+            writeln!(
+                f,
+                "{:<16}|{:>7}{:>7} |{:>7}{:>7} |{:>7}{:>7}",
+                term.name,
+                term.white.mg,
+                term.white.eg,
+                term.black.mg,
+                term.black.eg,
+                total.mg,
+                total.eg
+            )?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Phase: {}/{MAX_PHASE}", self.phase)?;
+
+        if self.kbnk != 0 {
+            writeln!(f, "KBNK mate: {:+}", self.kbnk)?;
+        }
+
+        Ok(())
+    }
+}
+
+// the material and piece-square score of every piece on the board, for white
+pub fn material_and_psqt(board: &Board, weights: &EvalWeights) -> Score {
+    let mut score = Score::default();
 
     for sq in 0..64 {
         let square = unsafe { Square::new(sq) }; // safety: square is always 0..=63
 
-        if let (Some(piece), Some(piece_colour)) = (board.piece_on(square), board.color_on(square))
-        {
-            let piece_score = match piece {
-                Piece::Pawn => 100,
-                Piece::Knight => 320,
-                Piece::Bishop => 330,
-                Piece::Rook => 500,
-                Piece::Queen => 900,
-                Piece::King => 20000,
-            } + piece_square(piece, piece_colour, square, is_endgame);
-
-            score += match piece_colour {
-                Color::White => piece_score,
-                Color::Black => -piece_score,
-            };
+        if let (Some(piece), Some(piece_colour)) = (board.piece_on(square), board.color_on(square))
+        {
+            score += piece_score(piece, piece_colour, square, weights);
+        }
+    }
+
+    score
+}
+
+// the material and piece-square score after making a move, from the score before it. only the
+// squares the move touches change: the moving piece leaves its source and arrives at the
+// destination, possibly promoted, anything captured is removed, and castling moves the rook too
+pub fn material_and_psqt_after(
+    board: &Board,
+    mov: ChessMove,
+    psqt: Score,
+    weights: &EvalWeights,
+) -> Score {
+    let colour = board.side_to_move();
+
+    let source = mov.get_source();
+    let dest = mov.get_dest();
+
+    let piece = board.piece_on(source).unwrap(); // the move is legal, so there's a piece to move
+
+    if let Some(castle) = castling(board, mov) {
+        let (king, king_dest) = castle.king;
+        let (rook, rook_dest) = castle.rook;
+
+        return psqt
+            + -piece_score(Piece::King, colour, king, weights)
+            + piece_score(Piece::King, colour, king_dest, weights)
+            + -piece_score(Piece::Rook, colour, rook, weights)
+            + piece_score(Piece::Rook, colour, rook_dest, weights);
+    }
+
+    let mut score = psqt
+        + -piece_score(piece, colour, source, weights)
+        + piece_score(mov.get_promotion().unwrap_or(piece), colour, dest, weights);
+
+    if let Some(captured) = board.piece_on(dest) {
+        score += -piece_score(captured, !colour, dest, weights);
+    } else if piece == Piece::Pawn && source.get_file() != dest.get_file() {
+        // en passant, the captured pawn is beside the source rather than on the destination
+        let captured_square = Square::make_square(source.get_rank(), dest.get_file());
+
+        score += -piece_score(Piece::Pawn, !colour, captured_square, weights);
+    }
+
+    score
+}
+
+// a piece's material and piece-square score, negative for black
+fn piece_score(piece: Piece, colour: Color, square: Square, weights: &EvalWeights) -> Score {
+    let index = match colour {
+        Color::White => 63 - square.to_index(),
+        Color::Black => square.to_index(),
+    };
+
+    let score =
+        weights.piece_values[piece.to_index()] + weights.piece_square[piece.to_index()][index];
+
+    match colour {
+        Color::White => score,
+        Color::Black => -score,
+    }
+}
+
+// the terms that only depend on where the pawns are, which are the same for every position
+// sharing a pawn skeleton, so they're cached by a key of just the pawns. the shield also looks
+// at the king, so it can't be cached here
+fn pawn_structure(board: &Board, pawn_table: &mut PawnHashTable) -> Score {
+    let key = pawn_key(board);
+
+    if let Some(score) = pawn_table.probe(key) {
+        return score;
+    }
+
+    let score =
+        pawn_structure_side(board, Color::White) + -pawn_structure_side(board, Color::Black);
+
+    pawn_table.store(key, score);
+
+    score
+}
+
+fn pawn_structure_side(board: &Board, colour: Color) -> Score {
+    passed_pawns(board, colour)
+        + doubled_pawns(board, colour)
+        + isolated_pawns(board, colour)
+        + backward_pawns(board, colour)
+}
+
+#[derive(Clone, Copy, Default)]
+struct PawnHashEntry {
+    key: u64,     // pawn-only zobrist key
+    score: Score, // pawn-structure score for white
+}
+
+pub struct PawnHashTable {
+    entries: Vec<PawnHashEntry>,
+    probes: u64, // lookups since the table was made
+    hits: u64,   // lookups that found the pawn skeleton
+}
+
+impl Default for PawnHashTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PawnHashTable {
+    pub fn new() -> Self {
+        Self {
+            entries: vec![PawnHashEntry::default(); PAWN_HASH_ENTRIES],
+            probes: 0,
+            hits: 0,
+        }
+    }
+
+    pub fn hit_rate(&self) -> f64 {
+        if self.probes == 0 {
+            0.0
+        } else {
+            self.hits as f64 / self.probes as f64
+        }
+    }
+
+    fn probe(&mut self, key: u64) -> Option<Score> {
+        self.probes += 1;
+
+        let entry = self.entries[key as usize & (PAWN_HASH_ENTRIES - 1)];
+
+        (entry.key == key).then(|| {
+            self.hits += 1;
+            entry.score
+        })
+    }
+
+    fn store(&mut self, key: u64, score: Score) {
+        self.entries[key as usize & (PAWN_HASH_ENTRIES - 1)] = PawnHashEntry { key, score };
+    }
+}
+
+// xor of a random number for every pawn's colour and square, like the full board hash but
+// ignoring the other pieces
+fn pawn_key(board: &Board) -> u64 {
+    static PAWN_KEYS: OnceLock<[[u64; 64]; 2]> = OnceLock::new();
+
+    let keys = PAWN_KEYS.get_or_init(|| {
+        let mut keys = [[0; 64]; 2];
+
+        // splitmix64, any fixed sequence of well mixed numbers will do
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+
+        for key in keys.iter_mut().flatten() {
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+
+            *key = z ^ (z >> 31);
+        }
+
+        keys
+    });
+
+    let mut key = 0;
+
+    for colour in [Color::White, Color::Black] {
+        for square in board.pieces(Piece::Pawn) & board.color_combined(colour) {
+            key ^= keys[colour.to_index()][square.to_index()];
+        }
+    }
+
+    key
+}
+
+// how far the game is from the endgame, from MAX_PHASE with all the pieces on the board down
+// to 0 with only kings and pawns left. extra pieces from promotions can't push it past the max
+pub fn game_phase(board: &Board) -> i32 {
+    let count = |piece| board.pieces(piece).0.count_ones() as i32;
+
+    let phase = count(Piece::Knight) * KNIGHT_PHASE
+        + count(Piece::Bishop) * BISHOP_PHASE
+        + count(Piece::Rook) * ROOK_PHASE
+        + count(Piece::Queen) * QUEEN_PHASE;
+
+    phase.min(MAX_PHASE)
+}
+
+// a pair of midgame and endgame scores, blended by the game phase once everything is added up
+// so the evaluation changes smoothly as material comes off instead of jumping at some cutoff
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct Score {
+    mg: i32,
+    eg: i32,
+}
+
+impl Score {
+    fn taper(self, phase: i32) -> i32 {
+        (self.mg * phase + self.eg * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl Add for Score {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            mg: self.mg + other.mg,
+            eg: self.eg + other.eg,
+        }
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Self;
+
+    fn mul(self, factor: i32) -> Self {
+        Self {
+            mg: self.mg * factor,
+            eg: self.eg * factor,
+        }
+    }
+}
+
+impl Neg for Score {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self {
+            mg: -self.mg,
+            eg: -self.eg,
+        }
+    }
+}
+
+fn piece_value(piece: Piece) -> i32 {
+    match piece {
+        Piece::Pawn => 100,
+        Piece::Knight => 320,
+        Piece::Bishop => 330,
+        Piece::Rook => 500,
+        Piece::Queen => 900,
+        Piece::King => 20000,
+    }
+}
+
+// pawns gain value as the board empties and they get closer to promoting, while the minor
+// pieces are slightly less useful without targets
+fn piece_value_endgame(piece: Piece) -> i32 {
+    match piece {
+        Piece::Pawn => 120,
+        Piece::Knight => 300,
+        Piece::Bishop => 320,
+        Piece::Rook => 520,
+        Piece::Queen => 920,
+        Piece::King => 20000,
+    }
+}
+
+// rewards pieces for the squares they can move to, not counting squares attacked by enemy
+// pawns since a piece can rarely go there safely. the count is taken relative to a typical
+// value so mobility only shifts the eval around the material value rather than inflating it
+fn mobility(board: &Board, colour: Color) -> Score {
+    let ours = *board.color_combined(colour);
+    let blockers = *board.combined();
+
+    let enemy_pawns = board.pieces(Piece::Pawn) & board.color_combined(!colour);
+    let available = !ours & !pawn_attacks(enemy_pawns, !colour);
+
+    let mut score = Score::default();
+
+    for square in ours & (board.pieces(Piece::Knight) | board.pieces(Piece::Bishop)) {
+        let piece = board.piece_on(square).unwrap();
+
+        let moves = match piece {
+            Piece::Knight => get_knight_moves(square),
+            _ => get_bishop_moves(square, blockers),
+        };
+
+        score += mobility_score(piece, (moves & available).popcnt() as i32);
+    }
+
+    for square in ours & (board.pieces(Piece::Rook) | board.pieces(Piece::Queen)) {
+        let piece = board.piece_on(square).unwrap();
+
+        let moves = match piece {
+            Piece::Rook => get_rook_moves(square, blockers),
+            _ => get_rook_moves(square, blockers) | get_bishop_moves(square, blockers),
+        };
+
+        score += mobility_score(piece, (moves & available).popcnt() as i32);
+    }
+
+    score
+}
+
+fn mobility_score(piece: Piece, moves: i32) -> Score {
+    let (typical_moves, weight) = match piece {
+        Piece::Knight => (4, KNIGHT_MOBILITY),
+        Piece::Bishop => (6, BISHOP_MOBILITY),
+        Piece::Rook => (7, ROOK_MOBILITY),
+        _ => (13, QUEEN_MOBILITY),
+    };
+
+    weight * (moves - typical_moves)
+}
+
+const KNIGHT_MOBILITY: Score = Score { mg: 4, eg: 4 };
+const BISHOP_MOBILITY: Score = Score { mg: 5, eg: 5 };
+const ROOK_MOBILITY: Score = Score { mg: 2, eg: 4 };
+const QUEEN_MOBILITY: Score = Score { mg: 1, eg: 2 };
+
+// penalises a king for the enemy pieces bearing down on the squares around it. each attacked
+// square adds the attacker's weight, and the total is looked up in a table that grows faster
+// than linearly, since one attacker is rarely dangerous but several together often are. only
+// applies in the midgame, in the endgame the king is meant to be active
+fn king_safety(board: &Board, colour: Color) -> Score {
+    let king = board.king_square(colour);
+    let zone = get_king_moves(king) | BitBoard::from_square(king);
+
+    let blockers = *board.combined();
+
+    let mut attack_weight = 0;
+
+    for square in *board.color_combined(!colour) {
+        let (attacks, weight) = match board.piece_on(square) {
+            Some(Piece::Knight) => (get_knight_moves(square), KNIGHT_ATTACK_WEIGHT),
+            Some(Piece::Bishop) => (get_bishop_moves(square, blockers), BISHOP_ATTACK_WEIGHT),
+            Some(Piece::Rook) => (get_rook_moves(square, blockers), ROOK_ATTACK_WEIGHT),
+            Some(Piece::Queen) => (
+                get_bishop_moves(square, blockers) | get_rook_moves(square, blockers),
+                QUEEN_ATTACK_WEIGHT,
+            ),
+            _ => continue,
+        };
+
+        attack_weight += (attacks & zone).popcnt() as usize * weight;
+    }
+
+    Score {
+        mg: -KING_SAFETY_TABLE[attack_weight.min(KING_SAFETY_TABLE.len() - 1)],
+        eg: 0,
+    }
+}
+
+// a king tucked away on either wing wants its pawns on the files in front of it to stay at
+// home, each step one of them has advanced leaves more holes around the king. a king still in
+// the centre has no shield to speak of. midgame only, like the attacks on the king zone
+fn pawn_shield(board: &Board, colour: Color) -> Score {
+    let king_file = board.king_square(colour).get_file().to_index();
+
+    if (3..=4).contains(&king_file) {
+        return Score {
+            mg: -CENTRAL_KING_PENALTY,
+            eg: 0,
+        };
+    }
+
+    let pawns = board.pieces(Piece::Pawn) & board.color_combined(colour);
+
+    let mut penalty = 0;
+
+    for file in king_file.saturating_sub(1)..=(king_file + 1).min(7) {
+        // the least advanced pawn on the file is the one actually covering the king
+        let advance = (pawns & BitBoard(FILE_A << file))
+            .map(|square| match colour {
+                Color::White => square.get_rank().to_index() - 1,
+                Color::Black => 6 - square.get_rank().to_index(),
+            })
+            .min()
+            .unwrap_or(SHIELD_PENALTY.len() - 1);
+
+        penalty += SHIELD_PENALTY[advance.min(SHIELD_PENALTY.len() - 1)];
+    }
+
+    Score {
+        mg: -penalty,
+        eg: 0,
+    }
+}
+
+const CENTRAL_KING_PENALTY: i32 = 30;
+
+// by how many ranks the shield pawn has advanced, with the last entry also used when the file
+// has no pawn at all
+const SHIELD_PENALTY: [i32; 4] = [0, 10, 25, 35];
+
+const KNIGHT_ATTACK_WEIGHT: usize = 2;
+const BISHOP_ATTACK_WEIGHT: usize = 2;
+const ROOK_ATTACK_WEIGHT: usize = 3;
+const QUEEN_ATTACK_WEIGHT: usize = 5;
+
+const KING_SAFETY_TABLE: [i32; 40] = [
+    0, 0, 1, 2, 3, 5, 7, 9, 12, 15, 18, 22, 26, 30, 35, 39, 44, 50, 56, 62, 68, 75, 82, 89, 97,
+    105, 113, 122, 131, 140, 150, 160, 170, 180, 190, 200, 210, 220, 230, 240,
+];
+
+// rooks want files without pawns in the way, where they can get at the enemy position. with
+// only enemy pawns on the file the rook still presses on the front one, which is an easy
+// target if it is isolated
+fn rook_files(board: &Board, colour: Color) -> Score {
+    let rooks = board.pieces(Piece::Rook) & board.color_combined(colour);
+
+    let pawns = board.pieces(Piece::Pawn) & board.color_combined(colour);
+    let enemy_pawns = board.pieces(Piece::Pawn) & board.color_combined(!colour);
+
+    let mut score = Score::default();
+
+    for square in rooks {
+        let file_index = square.get_file().to_index();
+        let file = BitBoard(FILE_A << file_index);
+
+        if pawns & file != EMPTY {
+            continue;
+        }
+
+        if enemy_pawns & file == EMPTY {
+            score += ROOK_OPEN_FILE_BONUS;
+        } else {
+            score += ROOK_SEMI_OPEN_FILE_BONUS;
+
+            if enemy_pawns & adjacent_files(file_index) == EMPTY {
+                score += ROOK_ISOLATED_PAWN_BONUS;
+            }
+        }
+    }
+
+    score
+}
+
+// a rook on the 7th rank from our side attacks the pawns still at home and keeps the enemy king
+// confined to the back rank
+fn rooks_on_seventh(board: &Board, colour: Color) -> Score {
+    let (seventh, eighth) = match colour {
+        Color::White => (RANK_1 << (8 * 6), RANK_1 << (8 * 7)),
+        Color::Black => (RANK_1 << 8, RANK_1),
+    };
+
+    let rooks = board.pieces(Piece::Rook) & board.color_combined(colour);
+
+    let rooks_on_seventh = (rooks & BitBoard(seventh)).popcnt() as i32;
+
+    if rooks_on_seventh == 0 {
+        return Score::default();
+    }
+
+    let mut score = ROOK_ON_SEVENTH_BONUS * rooks_on_seventh;
+
+    if BitBoard::from_square(board.king_square(!colour)) & BitBoard(eighth) != EMPTY {
+        score += ROOK_ON_SEVENTH_KING_BONUS;
+    }
+
+    if rooks_on_seventh > 1 {
+        score += ROOKS_DOUBLED_ON_SEVENTH_BONUS;
+    }
+
+    score
+}
+
+fn knight_outposts(board: &Board, colour: Color) -> Score {
+    let knights = board.pieces(Piece::Knight) & board.color_combined(colour);
+
+    let mut score = Score::default();
+
+    for square in knights & outposts(board, colour) {
+        score += KNIGHT_OUTPOST_BONUS;
+
+        if (2..=5).contains(&square.get_file().to_index()) {
+            score += KNIGHT_CENTRAL_OUTPOST_BONUS;
+        }
+    }
+
+    score
+}
+
+fn trapped_bishops(board: &Board, colour: Color) -> Score {
+    let bishops = board.pieces(Piece::Bishop) & board.color_combined(colour);
+    let enemy_pawns = board.pieces(Piece::Pawn) & board.color_combined(!colour);
+
+    let relative = |square: Square| match colour {
+        Color::White => square,
+        Color::Black => unsafe { Square::new(square.to_int() ^ 56) }, // safety: still 0..=63
+    };
+
+    let mut score = Score::default();
+
+    for (bishop, pawn) in TRAPPED_BISHOP_SQUARES {
+        if bishops & BitBoard::from_square(relative(bishop)) != EMPTY
+            && enemy_pawns & BitBoard::from_square(relative(pawn)) != EMPTY
+        {
+            score += -TRAPPED_BISHOP_PENALTY;
+        }
+    }
+
+    score
+}
+
+// our own pawns on the bishop's square colour block its diagonals, and can't be defended by it
+fn bad_bishops(board: &Board, colour: Color) -> Score {
+    let bishops = board.pieces(Piece::Bishop) & board.color_combined(colour);
+    let pawns = board.pieces(Piece::Pawn) & board.color_combined(colour);
+
+    let mut score = Score::default();
+
+    for square in bishops {
+        let same_colour = if BitBoard::from_square(square) & LIGHT_SQUARES != EMPTY {
+            LIGHT_SQUARES
+        } else {
+            !LIGHT_SQUARES
+        };
+
+        score += -(BAD_BISHOP_PAWN_PENALTY * (pawns & same_colour).popcnt() as i32);
+    }
+
+    score
+}
+
+// material isn't simply additive. knights want outposts and targets, which more pawns give them,
+// while rooks want open files, which more pawns take away. a pair of bishops covers both square
+// colours, which counts for more once pawns come off and open the board, and for more again
+// when the opponent has no minor pieces left to contest it
+fn imbalance(board: &Board, colour: Color) -> Score {
+    let count =
+        |piece, colour| (board.pieces(piece) & board.color_combined(colour)).popcnt() as i32;
+
+    let extra_pawns = count(Piece::Pawn, colour) - IMBALANCE_BASE_PAWNS;
+
+    let mut score = KNIGHT_PAWN_ADJUSTMENT * (count(Piece::Knight, colour) * extra_pawns)
+        + ROOK_PAWN_ADJUSTMENT * (count(Piece::Rook, colour) * extra_pawns);
+
+    if count(Piece::Bishop, colour) >= 2 {
+        score += BISHOP_PAIR_BONUS
+            + BISHOP_PAIR_PAWN_ADJUSTMENT * board.pieces(Piece::Pawn).popcnt() as i32;
+
+        if count(Piece::Knight, !colour) + count(Piece::Bishop, !colour) == 0 {
+            score += BISHOP_PAIR_NO_ENEMY_MINORS_BONUS;
         }
     }
 
-    match board.side_to_move() {
-        Color::White => score,
-        Color::Black => -score,
+    score
+}
+
+// squares on the 4th to 6th rank from our side that one of our pawns defends and no enemy pawn
+// can ever attack, because there are none left on the adjacent files in front of the square.
+// a piece there can only be driven away by trading it off
+fn outposts(board: &Board, colour: Color) -> BitBoard {
+    let pawns = board.pieces(Piece::Pawn) & board.color_combined(colour);
+    let enemy_pawns = board.pieces(Piece::Pawn) & board.color_combined(!colour);
+
+    let enemy_territory = match colour {
+        Color::White => RANK_1 << (8 * 3) | RANK_1 << (8 * 4) | RANK_1 << (8 * 5),
+        Color::Black => RANK_1 << (8 * 2) | RANK_1 << (8 * 3) | RANK_1 << (8 * 4),
+    };
+
+    let candidates = BitBoard(enemy_territory) & pawn_attacks(pawns, colour);
+
+    let mut outposts = EMPTY;
+
+    for square in candidates {
+        let attack_span =
+            passed_pawn_mask(square, colour) & adjacent_files(square.get_file().to_index());
+
+        if enemy_pawns & attack_span == EMPTY {
+            outposts |= BitBoard::from_square(square);
+        }
+    }
+
+    outposts
+}
+
+// a pawn with no enemy pawns in front of it on its own or the adjacent files can only be
+// stopped by pieces, which matters more and more the closer it gets to promoting. one that
+// another pawn defends can't simply be picked off, and one with a neighbour level with or
+// behind it can be escorted forward by that pawn
+fn passed_pawns(board: &Board, colour: Color) -> Score {
+    let pawns = board.pieces(Piece::Pawn) & board.color_combined(colour);
+    let enemy_pawns = board.pieces(Piece::Pawn) & board.color_combined(!colour);
+
+    let defended = pawn_attacks(pawns, colour);
+
+    let mut score = Score::default();
+
+    for square in pawns {
+        if passed_pawn_mask(square, colour) & enemy_pawns != EMPTY {
+            continue;
+        }
+
+        let rank = match colour {
+            Color::White => square.get_rank().to_index(),
+            Color::Black => 7 - square.get_rank().to_index(),
+        };
+
+        score += PASSED_PAWN_BONUS[rank];
+
+        if defended & BitBoard::from_square(square) != EMPTY {
+            score += PROTECTED_PASSED_PAWN_BONUS;
+        }
+
+        let level_or_behind = passed_pawn_mask(square, !colour)
+            | BitBoard(RANK_1 << (8 * square.get_rank().to_index()));
+
+        if pawns & adjacent_files(square.get_file().to_index()) & level_or_behind != EMPTY {
+            score += CONNECTED_PASSED_PAWN_BONUS;
+        }
     }
+
+    score
 }
 
-fn piece_square(piece: Piece, piece_colour: Color, square: Square, is_endgame: bool) -> i32 {
-    let table = match piece {
-        Piece::Pawn => PAWN_TABLE,
-        Piece::Knight => KNIGHT_TABLE,
-        Piece::Bishop => BISHOP_TABLE,
-        Piece::Rook => ROOK_TABLE,
-        Piece::Queen => QUEEN_TABLE,
-        Piece::King => {
-            if is_endgame {
-                KING_TABLE_ENDGAME
-            } else {
-                KING_TABLE
+// the squares in front of a pawn on its own and the adjacent files, for each colour
+fn passed_pawn_mask(square: Square, colour: Color) -> BitBoard {
+    static PASSED_PAWN_MASKS: OnceLock<[[BitBoard; 64]; 2]> = OnceLock::new();
+
+    let masks = PASSED_PAWN_MASKS.get_or_init(|| {
+        let mut masks = [[EMPTY; 64]; 2];
+
+        for (index, square) in ALL_SQUARES.iter().enumerate() {
+            let file = square.get_file().to_index();
+            let rank = square.get_rank().to_index();
+
+            let files = file.saturating_sub(1)..=(file + 1).min(7);
+
+            for other in ALL_SQUARES {
+                if !files.contains(&other.get_file().to_index()) {
+                    continue;
+                }
+
+                let other_rank = other.get_rank().to_index();
+
+                if other_rank > rank {
+                    masks[Color::White.to_index()][index] |= BitBoard::from_square(other);
+                }
+
+                if other_rank < rank {
+                    masks[Color::Black.to_index()][index] |= BitBoard::from_square(other);
+                }
             }
         }
-    };
 
-    let index = match piece_colour {
-        Color::White => 63 - square.to_index(),
-        Color::Black => square.to_index(),
-    };
+        masks
+    });
+
+    masks[colour.to_index()][square.to_index()]
+}
+
+// pawns stacked on one file can't protect each other and the front one blocks the rest
+fn doubled_pawns(board: &Board, colour: Color) -> Score {
+    let pawns = board.pieces(Piece::Pawn) & board.color_combined(colour);
+
+    let extra_pawns = (0..8)
+        .map(|file| {
+            (pawns & BitBoard(FILE_A << file))
+                .popcnt()
+                .saturating_sub(1) as i32
+        })
+        .sum::<i32>();
+
+    -(DOUBLED_PAWN_PENALTY * extra_pawns)
+}
+
+// a pawn with no friendly pawns on the adjacent files can only be defended by pieces. it's
+// worse when there are no enemy pawns on its file either, since enemy rooks can then attack
+// it down the file
+fn isolated_pawns(board: &Board, colour: Color) -> Score {
+    let pawns = board.pieces(Piece::Pawn) & board.color_combined(colour);
+    let enemy_pawns = board.pieces(Piece::Pawn) & board.color_combined(!colour);
+
+    let mut score = Score::default();
+
+    for square in pawns {
+        let file = square.get_file().to_index();
+
+        if pawns & adjacent_files(file) != EMPTY {
+            continue;
+        }
+
+        score += if enemy_pawns & BitBoard(FILE_A << file) == EMPTY {
+            -ISOLATED_HALF_OPEN_PAWN_PENALTY
+        } else {
+            -ISOLATED_PAWN_PENALTY
+        };
+    }
+
+    score
+}
+
+// a pawn whose neighbours have all advanced past it can't be supported by them, and if an enemy
+// pawn controls the square in front of it, it can't advance to catch up either. it's stuck as
+// a target, made worse when enemy rooks can attack it down a half-open file. isolated pawns
+// have no neighbours at all and are already penalised for it
+fn backward_pawns(board: &Board, colour: Color) -> Score {
+    let pawns = board.pieces(Piece::Pawn) & board.color_combined(colour);
+    let enemy_pawns = board.pieces(Piece::Pawn) & board.color_combined(!colour);
+
+    let enemy_pawn_attacks = pawn_attacks(enemy_pawns, !colour);
+
+    let mut score = Score::default();
+
+    for square in pawns {
+        let file = square.get_file().to_index();
+        let rank = square.get_rank().to_index();
+
+        let neighbours = pawns & adjacent_files(file);
+
+        // the squares on the adjacent files level with or behind the pawn, from which a
+        // neighbour could still advance to defend it
+        let support_span = (passed_pawn_mask(square, !colour) | BitBoard(RANK_1 << (8 * rank)))
+            & adjacent_files(file);
+
+        let stop_square_attacked = square
+            .forward(colour)
+            .is_some_and(|stop| enemy_pawn_attacks & BitBoard::from_square(stop) != EMPTY);
+
+        if neighbours == EMPTY || neighbours & support_span != EMPTY || !stop_square_attacked {
+            continue;
+        }
+
+        score += if enemy_pawns & BitBoard(FILE_A << file) == EMPTY {
+            -BACKWARD_HALF_OPEN_PAWN_PENALTY
+        } else {
+            -BACKWARD_PAWN_PENALTY
+        };
+    }
+
+    score
+}
+
+fn adjacent_files(file: usize) -> BitBoard {
+    let left = if file > 0 { FILE_A << (file - 1) } else { 0 };
+    let right = if file < 7 { FILE_A << (file + 1) } else { 0 };
+
+    BitBoard(left | right)
+}
+
+const PROTECTED_PASSED_PAWN_BONUS: Score = Score { mg: 10, eg: 25 };
+const CONNECTED_PASSED_PAWN_BONUS: Score = Score { mg: 8, eg: 20 };
+
+// by rank from the pawn's own side, pawns can't be on the first or last rank
+const PASSED_PAWN_BONUS: [Score; 8] = [
+    Score { mg: 0, eg: 0 },
+    Score { mg: 5, eg: 10 },
+    Score { mg: 10, eg: 20 },
+    Score { mg: 20, eg: 40 },
+    Score { mg: 35, eg: 70 },
+    Score { mg: 60, eg: 120 },
+    Score { mg: 100, eg: 180 },
+    Score { mg: 0, eg: 0 },
+];
+
+// squares attacked by the given pawns, shifting the whole set diagonally at once and dropping
+// the ones that wrapped around the board edge
+fn pawn_attacks(pawns: BitBoard, colour: Color) -> BitBoard {
+    let pawns = pawns.0;
+
+    BitBoard(match colour {
+        Color::White => ((pawns << 7) & !FILE_H) | ((pawns << 9) & !FILE_A),
+        Color::Black => ((pawns >> 9) & !FILE_H) | ((pawns >> 7) & !FILE_A),
+    })
+}
+
+const FILE_A: u64 = 0x0101_0101_0101_0101;
+const FILE_H: u64 = FILE_A << 7;
+const RANK_1: u64 = 0xff;
+
+// in kbn vs k the lone king can only be mated in a corner of the bishop's colour, so reward
+// pushing it towards the nearest such corner and bringing the attacking king along
+fn kbnk_mate(board: &Board) -> i32 {
+    if board.combined().0.count_ones() != 4 {
+        return 0;
+    }
+
+    for strong_side in [Color::White, Color::Black] {
+        let ours = *board.color_combined(strong_side);
+
+        let bishops = board.pieces(Piece::Bishop) & ours;
+        let knights = board.pieces(Piece::Knight) & ours;
+
+        if ours.0.count_ones() != 3 || bishops.0.count_ones() != 1 || knights.0.count_ones() != 1 {
+            continue;
+        }
+
+        let bishop = bishops.to_square();
+        let weak_king = board.king_square(!strong_side);
+        let strong_king = board.king_square(strong_side);
+
+        let light_bishop = (bishop.get_file().to_index() + bishop.get_rank().to_index()) % 2 == 1;
+
+        let corners = if light_bishop {
+            [Square::H1, Square::A8]
+        } else {
+            [Square::A1, Square::H8]
+        };
+
+        let corner_distance = corners
+            .iter()
+            .map(|&corner| manhattan_distance(weak_king, corner))
+            .min()
+            .unwrap();
+
+        // the wrong corners are as far from the right ones as the centre is, so without pushing
+        // the king to the edge as well it could sit in a wrong corner for good
+        let edge_distance = [
+            weak_king.get_file().to_index(),
+            weak_king.get_rank().to_index(),
+        ]
+        .into_iter()
+        .map(|index| index.min(7 - index) as i32)
+        .min()
+        .unwrap();
+
+        let bonus = KBNK_CORNER_WEIGHT * (14 - corner_distance)
+            + KBNK_EDGE_WEIGHT * (3 - edge_distance)
+            + KBNK_KING_PROXIMITY_WEIGHT * (7 - chebyshev_distance(strong_king, weak_king));
+
+        return match strong_side {
+            Color::White => bonus,
+            Color::Black => -bonus,
+        };
+    }
+
+    0
+}
+
+const KBNK_CORNER_WEIGHT: i32 = 100;
+const KBNK_EDGE_WEIGHT: i32 = 50;
+const KBNK_KING_PROXIMITY_WEIGHT: i32 = 30;
+
+fn manhattan_distance(a: Square, b: Square) -> i32 {
+    let file_distance = a.get_file().to_index() as i32 - b.get_file().to_index() as i32;
+    let rank_distance = a.get_rank().to_index() as i32 - b.get_rank().to_index() as i32;
+
+    file_distance.abs() + rank_distance.abs()
+}
+
+fn chebyshev_distance(a: Square, b: Square) -> i32 {
+    let file_distance = a.get_file().to_index() as i32 - b.get_file().to_index() as i32;
+    let rank_distance = a.get_rank().to_index() as i32 - b.get_rank().to_index() as i32;
+
+    file_distance.abs().max(rank_distance.abs())
+}
+
+// the piece values and piece-square tables, which can be loaded from a file to tune them
+// without rebuilding
+#[derive(Clone)]
+pub struct EvalWeights {
+    piece_values: [Score; 6],       // by piece
+    piece_square: [[Score; 64]; 6], // by piece, laid out like the built-in tables
+}
+
+impl Default for EvalWeights {
+    fn default() -> Self {
+        let tables = [
+            (PAWN_TABLE, PAWN_TABLE_ENDGAME),
+            (KNIGHT_TABLE, KNIGHT_TABLE_ENDGAME),
+            (BISHOP_TABLE, BISHOP_TABLE_ENDGAME),
+            (ROOK_TABLE, ROOK_TABLE_ENDGAME),
+            (QUEEN_TABLE, QUEEN_TABLE_ENDGAME),
+            (KING_TABLE, KING_TABLE_ENDGAME),
+        ];
+
+        let mut weights = Self {
+            piece_values: [Score::default(); 6],
+            piece_square: [[Score::default(); 64]; 6],
+        };
+
+        for piece in ALL_PIECES {
+            weights.piece_values[piece.to_index()] = Score {
+                mg: piece_value(piece),
+                eg: piece_value_endgame(piece),
+            };
 
-    table[index]
+            let (table, table_endgame) = tables[piece.to_index()];
+
+            for (index, score) in weights.piece_square[piece.to_index()]
+                .iter_mut()
+                .enumerate()
+            {
+                *score = Score {
+                    mg: table[index],
+                    eg: table_endgame[index],
+                };
+            }
+        }
+
+        weights
+    }
+}
+
+impl EvalWeights {
+    pub const PARAMETERS: usize = 6 * 2 + 6 * 64 * 2; // piece values then tables, mg then eg
+
+    // the midgame value, which is also what exchanges are weighed with
+    pub fn piece_value(&self, piece: Piece) -> i32 {
+        self.piece_values[piece.to_index()].mg
+    }
+
+    // sets a piece's midgame value, moving its endgame value by the same amount
+    pub fn set_piece_value(&mut self, piece: Piece, value: i32) {
+        let score = &mut self.piece_values[piece.to_index()];
+
+        score.eg += value - score.mg;
+        score.mg = value;
+    }
+
+    // every weight in a flat order, so a tuner can step through them
+    pub fn parameter_mut(&mut self, index: usize) -> &mut i32 {
+        let (score, index) = if index < 12 {
+            (&mut self.piece_values[index / 2], index)
+        } else {
+            let index = index - 12;
+
+            (&mut self.piece_square[index / 128][index % 128 / 2], index)
+        };
+
+        if index % 2 == 0 {
+            &mut score.mg
+        } else {
+            &mut score.eg
+        }
+    }
+}
+
+// writes the weights in the format load_eval_weights reads
+impl fmt::Display for EvalWeights {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, value) in PIECE_NAMES.iter().zip(self.piece_values) {
+            writeln!(f, "value {name} {} {}", value.mg, value.eg)?;
+        }
+
+        for (name, table) in PIECE_NAMES.iter().zip(self.piece_square) {
+            let mg = table.map(|score| score.mg.to_string()).join(" ");
+            let eg = table.map(|score| score.eg.to_string()).join(" ");
+
+            writeln!(f, "table {name} mg {mg}")?;
+            writeln!(f, "table {name} eg {eg}")?;
+        }
+
+        Ok(())
+    }
+}
+
+// in the order of chess::ALL_PIECES
+const PIECE_NAMES: [&str; 6] = ["pawn", "knight", "bishop", "rook", "queen", "king"];
+
+// reads weights from a text file, starting from the built-in ones so the file only needs to
+// list what it changes. blank lines and lines starting with # are skipped, every other line is
+// one of
+//   value <piece> <midgame> <endgame>
+//   table <piece> <mg|eg> <64 values, in the order of the built-in tables>
+// where <piece> is pawn, knight, bishop, rook, queen or king
+pub fn load_eval_weights(path: &str) -> Result<EvalWeights, String> {
+    let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+
+    let mut weights = EvalWeights::default();
+
+    for (line_number, line) in contents.lines().enumerate() {
+        let line_number = line_number + 1;
+
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+
+        if tokens.is_empty() || tokens[0].starts_with('#') {
+            continue;
+        }
+
+        let piece = match PIECE_NAMES
+            .iter()
+            .position(|&name| tokens.get(1) == Some(&name))
+        {
+            Some(index) => ALL_PIECES[index],
+            None => return Err(format!("line {line_number}: expected a piece name")),
+        };
+
+        let values = |start: usize, count: usize| {
+            let values = tokens[start.min(tokens.len())..]
+                .iter()
+                .map(|token| token.parse::<i32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| format!("line {line_number}: {err}"))?;
+
+            if values.len() != count {
+                return Err(format!(
+                    "line {line_number}: expected {count} values, found {}",
+                    values.len()
+                ));
+            }
+
+            Ok(values)
+        };
+
+        match tokens[0] {
+            "value" => {
+                let values = values(2, 2)?;
+
+                weights.piece_values[piece.to_index()] = Score {
+                    mg: values[0],
+                    eg: values[1],
+                };
+            }
+            "table" => {
+                let values = values(3, 64)?;
+
+                let table = &mut weights.piece_square[piece.to_index()];
+
+                match tokens.get(2).copied() {
+                    Some("mg") => table.iter_mut().zip(values).for_each(|(s, v)| s.mg = v),
+                    Some("eg") => table.iter_mut().zip(values).for_each(|(s, v)| s.eg = v),
+                    _ => return Err(format!("line {line_number}: expected mg or eg")),
+                }
+            }
+            other => return Err(format!("line {line_number}: unknown entry {other}")),
+        }
+    }
+
+    Ok(weights)
 }
 
 const PAWN_TABLE: [i32; 64] = [
@@ -62,30 +1366,60 @@ const PAWN_TABLE: [i32; 64] = [
     -20, 10, 10, 5, 0, 0, 0, 0, 0, 0, 0, 0,
 ];
 
+const PAWN_TABLE_ENDGAME: [i32; 64] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 80, 80, 80, 80, 80, 80, 80, 80, 50, 50, 50, 50, 50, 50, 50, 50, 30, 30,
+    30, 30, 30, 30, 30, 30, 15, 15, 15, 15, 15, 15, 15, 15, 5, 5, 5, 5, 5, 5, 5, 5, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
 const KNIGHT_TABLE: [i32; 64] = [
     -50, -40, -30, -30, -30, -30, -40, -50, -40, -20, 0, 0, 0, 0, -20, -40, -30, 0, 10, 15, 15, 10,
     0, -30, -30, 5, 15, 20, 20, 15, 5, -30, -30, 0, 15, 20, 20, 15, 0, -30, -30, 5, 10, 15, 15, 10,
     5, -30, -40, -20, 0, 5, 5, 0, -20, -40, -50, -40, -30, -30, -30, -30, -40, -50,
 ];
 
+const KNIGHT_TABLE_ENDGAME: [i32; 64] = [
+    -50, -40, -30, -30, -30, -30, -40, -50, -40, -20, 0, 5, 5, 0, -20, -40, -30, 0, 10, 15, 15, 10,
+    0, -30, -30, 5, 15, 20, 20, 15, 5, -30, -30, 5, 15, 20, 20, 15, 5, -30, -30, 0, 10, 15, 15, 10,
+    0, -30, -40, -20, 0, 5, 5, 0, -20, -40, -50, -40, -30, -30, -30, -30, -40, -50,
+];
+
 const BISHOP_TABLE: [i32; 64] = [
     -20, -10, -10, -10, -10, -10, -10, -20, -10, 0, 0, 0, 0, 0, 0, -10, -10, 0, 5, 10, 10, 5, 0,
     -10, -10, 5, 5, 10, 10, 5, 5, -10, -10, 0, 10, 10, 10, 10, 0, -10, -10, 10, 10, 10, 10, 10, 10,
     -10, -10, 5, 0, 0, 0, 0, 5, -10, -20, -10, -10, -10, -10, -10, -10, -20,
 ];
 
+const BISHOP_TABLE_ENDGAME: [i32; 64] = [
+    -20, -10, -10, -10, -10, -10, -10, -20, -10, 0, 0, 0, 0, 0, 0, -10, -10, 0, 5, 10, 10, 5, 0,
+    -10, -10, 5, 10, 15, 15, 10, 5, -10, -10, 5, 10, 15, 15, 10, 5, -10, -10, 0, 5, 10, 10, 5, 0,
+    -10, -10, 0, 0, 0, 0, 0, 0, -10, -20, -10, -10, -10, -10, -10, -10, -20,
+];
+
 const ROOK_TABLE: [i32; 64] = [
     0, 0, 0, 0, 0, 0, 0, 0, 5, 10, 10, 10, 10, 10, 10, 5, -5, 0, 0, 0, 0, 0, 0, -5, -5, 0, 0, 0, 0,
     0, 0, -5, -5, 0, 0, 0, 0, 0, 0, -5, -5, 0, 0, 0, 0, 0, 0, -5, -5, 0, 0, 0, 0, 0, 0, -5, 0, 0,
     0, 5, 5, 0, 0, 0,
 ];
 
+const ROOK_TABLE_ENDGAME: [i32; 64] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 10, 10, 10, 10, 10, 10, 10, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0,
+];
+
 const QUEEN_TABLE: [i32; 64] = [
     -20, -10, -10, -5, -5, -10, -10, -20, -10, 0, 0, 0, 0, 0, 0, -10, -10, 0, 5, 5, 5, 5, 0, -10,
     -5, 0, 5, 5, 5, 5, 0, -5, 0, 0, 5, 5, 5, 5, 0, -5, -10, 5, 5, 5, 5, 5, 0, -10, -10, 0, 5, 0, 0,
     0, 0, -10, -20, -10, -10, -5, -5, -10, -10, -20,
 ];
 
+const QUEEN_TABLE_ENDGAME: [i32; 64] = [
+    -20, -10, -10, -5, -5, -10, -10, -20, -10, 0, 5, 5, 5, 5, 0, -10, -10, 5, 10, 10, 10, 10, 5,
+    -10, -5, 5, 10, 15, 15, 10, 5, -5, -5, 5, 10, 15, 15, 10, 5, -5, -10, 5, 10, 10, 10, 10, 5,
+    -10, -10, 0, 5, 5, 5, 5, 0, -10, -20, -10, -10, -5, -5, -10, -10, -20,
+];
+
 const KING_TABLE: [i32; 64] = [
     -30, -40, -40, -50, -50, -40, -40, -30, -30, -40, -40, -50, -50, -40, -40, -30, -30, -40, -40,
     -50, -50, -40, -40, -30, -30, -40, -40, -50, -50, -40, -40, -30, -20, -30, -30, -40, -40, -30,
@@ -100,36 +1434,293 @@ const KING_TABLE_ENDGAME: [i32; 64] = [
     -30, -50,
 ];
 
-fn is_endgame(board: &Board) -> bool {
-    if board.pieces(Piece::Queen).0.count_ones() == 0 {
-        true
-    } else {
-        let white_endgame = {
-            let white_minor_pieces = ((board.pieces(Piece::Knight) | board.pieces(Piece::Bishop))
-                & board.color_combined(Color::White))
-            .0
-            .count_ones();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uci::mate_in_moves;
+    use chess::BoardBuilder;
+    use std::str::FromStr;
+
+    fn board(fen: &str) -> Board {
+        Board::from_str(fen).unwrap()
+    }
 
-            let white_rooks = (board.pieces(Piece::Rook) & board.color_combined(Color::White))
-                .0
-                .count_ones();
+    // the same position with the board turned around and the colours swapped, which every term
+    // should score the same for the side to move
+    fn flip_colours(fen: &str) -> String {
+        let fields = fen.split_whitespace().collect::<Vec<_>>();
 
-            white_minor_pieces <= 1 && white_rooks == 0
+        let swap_case = |field: &str| {
+            field
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_uppercase() {
+                        c.to_ascii_lowercase()
+                    } else {
+                        c.to_ascii_uppercase()
+                    }
+                })
+                .collect::<String>()
         };
 
-        let black_endgame = {
-            let black_minor_pieces = ((board.pieces(Piece::Knight) | board.pieces(Piece::Bishop))
-                & board.color_combined(Color::Black))
-            .0
-            .count_ones();
+        let placement = fields[0].split('/').rev().collect::<Vec<_>>().join("/");
+
+        let side_to_move = if fields[1] == "w" { "b" } else { "w" };
+
+        let en_passant = fields[3]
+            .chars()
+            .map(|c| match c {
+                '3' => '6',
+                '6' => '3',
+                c => c,
+            })
+            .collect::<String>();
+
+        format!(
+            "{} {side_to_move} {} {en_passant} {}",
+            swap_case(&placement),
+            swap_case(fields[2]),
+            fields[4..].join(" ")
+        )
+    }
+
+    #[test]
+    fn white_and_side_to_move_evals_only_differ_in_sign() {
+        // black to move, and better off, so both the tempo and the sign matter
+        let fen = "r1bqk2r/pppp1ppp/2n2n2/2b1p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R b KQkq - 5 4";
+
+        let weights = EvalWeights::default();
+
+        let breakdown = evaluate_verbose(&board(fen), &weights, 0);
 
-            let black_rooks = (board.pieces(Piece::Rook) & board.color_combined(Color::Black))
-                .0
-                .count_ones();
+        assert_ne!(breakdown.side_to_move, 0);
+        assert_eq!(breakdown.white, -breakdown.side_to_move);
 
-            black_minor_pieces <= 1 && black_rooks == 0
+        // the search sees the same score for the side to move
+        let searched = evaluate_position(
+            &board(fen),
+            material_and_psqt(&board(fen), &weights),
+            0,
+            &mut PawnHashTable::new(),
+        );
+
+        assert_eq!(searched, breakdown.side_to_move);
+
+        // with the colours swapped it's white to move in the same position
+        let flipped = evaluate_verbose(&board(&flip_colours(fen)), &weights, 0);
+
+        assert_eq!(flipped.side_to_move, breakdown.side_to_move);
+        assert_eq!(flipped.white, -breakdown.white);
+    }
+
+    #[test]
+    fn extreme_evals_are_clamped_below_the_mate_threshold() {
+        // nine queens against a bare king, with a material score far beyond any real position
+        let queens = board("k7/8/8/8/8/2QQQ3/2QQQ3/K1QQQ3 w - - 0 1");
+
+        let extreme = Score {
+            mg: 20 * INFINITY,
+            eg: 20 * INFINITY,
         };
 
-        white_endgame && black_endgame
+        for (psqt, sign) in [(extreme, 1), (-extreme, -1)] {
+            let white = evaluate_white(&queens, psqt, &mut PawnHashTable::new());
+
+            assert_eq!(white, sign * MAX_EVAL);
+
+            let score = evaluate_position(&queens, psqt, 0, &mut PawnHashTable::new());
+
+            assert!(score.abs() < INFINITY / 2, "{score}");
+            assert_eq!(mate_in_moves(score), None);
+        }
+    }
+
+    #[test]
+    fn passed_pawns_score_by_rank() {
+        // nothing in front of the d pawn on its own or the adjacent files
+        let passed = board("4k3/8/8/3P4/8/8/8/4K3 w - - 0 1");
+
+        assert_eq!(passed_pawns(&passed, Color::White), PASSED_PAWN_BONUS[4]);
+
+        // the same for black, counted from black's side
+        let flipped = board(&flip_colours("4k3/8/8/3P4/8/8/8/4K3 w - - 0 1"));
+
+        assert_eq!(passed_pawns(&flipped, Color::Black), PASSED_PAWN_BONUS[4]);
+
+        // an enemy pawn on an adjacent file in front stops it being passed, one behind doesn't
+        let blocked = board("4k3/8/4p3/3P4/8/8/8/4K3 w - - 0 1");
+        let behind = board("4k3/8/8/3P4/4p3/8/8/4K3 w - - 0 1");
+
+        assert_eq!(passed_pawns(&blocked, Color::White), Score::default());
+        assert_eq!(passed_pawns(&behind, Color::White), PASSED_PAWN_BONUS[4]);
+
+        // worth more the closer it gets, and more in the endgame
+        let bonuses = [
+            "4k3/8/8/8/8/3P4/8/4K3 w - - 0 1",
+            "4k3/8/8/3P4/8/8/8/4K3 w - - 0 1",
+            "k7/3P4/8/8/8/8/8/4K3 w - - 0 1",
+        ]
+        .map(|fen| passed_pawns(&board(fen), Color::White));
+
+        assert!(bonuses.windows(2).all(|pair| pair[0].eg < pair[1].eg));
+        assert!(bonuses.iter().all(|bonus| bonus.eg > bonus.mg));
+
+        // one defended by a neighbour behind it is protected and connected, while the
+        // neighbour only has a pawn ahead of it
+        let supported = board("4k3/8/8/3P4/2P5/8/8/4K3 w - - 0 1");
+
+        assert_eq!(
+            passed_pawns(&supported, Color::White),
+            PASSED_PAWN_BONUS[4]
+                + PROTECTED_PASSED_PAWN_BONUS
+                + CONNECTED_PASSED_PAWN_BONUS
+                + PASSED_PAWN_BONUS[3]
+        );
+    }
+
+    #[test]
+    fn backward_pawns_are_penalised() {
+        // the c and e pawns have gone past the d pawn, and black's c pawn stops it advancing.
+        // nothing of black's on the d file, so rooks can get at it
+        let half_open = "4k3/8/8/2p5/2P1P3/3P4/8/4K3 w - - 0 1";
+
+        assert_eq!(
+            backward_pawns(&board(half_open), Color::White),
+            -BACKWARD_HALF_OPEN_PAWN_PENALTY
+        );
+
+        assert_eq!(
+            backward_pawns(&board(&flip_colours(half_open)), Color::Black),
+            -BACKWARD_HALF_OPEN_PAWN_PENALTY
+        );
+
+        // a black pawn further up the d file shields it
+        let closed = board("4k3/3p4/8/2p5/2P1P3/3P4/8/4K3 w - - 0 1");
+
+        assert_eq!(
+            backward_pawns(&closed, Color::White),
+            -BACKWARD_PAWN_PENALTY
+        );
+
+        for fen in [
+            // a neighbour level with it can still come to its defence
+            "4k3/8/8/2p5/2P1P3/3PP3/8/4K3 w - - 0 1",
+            // nothing controls the square in front of it
+            "4k3/8/8/8/2P1P3/3P4/8/4K3 w - - 0 1",
+            // an isolated pawn has no neighbours to fall behind
+            "4k3/8/8/2p5/8/3P4/8/4K3 w - - 0 1",
+        ] {
+            assert_eq!(
+                backward_pawns(&board(fen), Color::White),
+                Score::default(),
+                "{fen}"
+            );
+        }
+    }
+
+    #[test]
+    fn knights_on_outposts_are_rewarded() {
+        // the e pawn holds d5, and black has no c or e pawn left to drive the knight away
+        let central = "4k3/pp3ppp/3p4/3N4/4P3/8/PPP2PPP/4K3 w - - 0 1";
+
+        let outpost = KNIGHT_OUTPOST_BONUS + KNIGHT_CENTRAL_OUTPOST_BONUS;
+
+        assert_eq!(knight_outposts(&board(central), Color::White), outpost);
+
+        assert_eq!(
+            knight_outposts(&board(&flip_colours(central)), Color::Black),
+            outpost
+        );
+
+        assert!(
+            outposts(&board(central), Color::White) & BitBoard::from_square(Square::D5) != EMPTY
+        );
+
+        // on the edge it's worth less
+        let edge = board("4k3/p4ppp/8/N7/1P6/8/P4PPP/4K3 w - - 0 1");
+
+        assert_eq!(knight_outposts(&edge, Color::White), KNIGHT_OUTPOST_BONUS);
+
+        for fen in [
+            // c7 can still come to c6
+            "4k3/ppp2ppp/3p4/3N4/4P3/8/PPP2PPP/4K3 w - - 0 1",
+            // no pawn defends it
+            "4k3/pp3ppp/3p4/3N4/8/8/PPP2PPP/4K3 w - - 0 1",
+            // not in enemy territory
+            "4k3/pp3ppp/3p4/8/8/3N4/PPP1PPPP/4K3 w - - 0 1",
+        ] {
+            assert_eq!(
+                knight_outposts(&board(fen), Color::White),
+                Score::default(),
+                "{fen}"
+            );
+        }
+    }
+
+    #[test]
+    fn opposite_coloured_bishop_endings_are_scaled_down() {
+        // white is a pawn up with a light-squared bishop against a dark-squared one
+        let opposite = board("4k3/5p2/3b4/8/8/3B4/3P1P2/4K3 w - - 0 1");
+
+        // the same with black's bishop on a light square
+        let same = board("4k3/5p2/4b3/8/8/3B4/3P1P2/4K3 w - - 0 1");
+
+        assert!(is_opposite_coloured_bishop_ending(&opposite));
+        assert!(!is_opposite_coloured_bishop_ending(&same));
+
+        let tempo = TEMPO_BONUS.taper(game_phase(&opposite));
+
+        assert_eq!(
+            side_to_move_score(&opposite, 200, 0),
+            (200 + tempo) * OPPOSITE_BISHOPS_SCALE_PERCENT / 100
+        );
+        assert_eq!(side_to_move_score(&same, 200, 0), 200 + tempo);
+
+        // any other piece and it isn't a bishop ending any more
+        for fen in [
+            "4k3/5p2/3b4/8/8/3B4/3P1P2/4KN2 w - - 0 1",
+            "3rk3/5p2/3b4/8/8/3B4/3P1P2/4K3 w - - 0 1",
+            "4k3/5p2/3b4/8/8/3BB3/3P1P2/4K3 w - - 0 1",
+        ] {
+            assert!(!is_opposite_coloured_bishop_ending(&board(fen)), "{fen}");
+        }
+    }
+
+    #[test]
+    fn bishops_trapped_in_each_corner_are_penalised() {
+        for (fen, colour) in [
+            ("4k3/B7/1p6/8/8/8/8/4K3 w - - 0 1", Color::White),
+            ("4k3/7B/6p1/8/8/8/8/4K3 w - - 0 1", Color::White),
+            ("4k3/8/B7/1p6/8/8/8/4K3 w - - 0 1", Color::White),
+            ("4k3/8/7B/6p1/8/8/8/4K3 w - - 0 1", Color::White),
+            ("4k3/8/8/8/8/1P6/b7/4K3 b - - 0 1", Color::Black),
+            ("4k3/8/8/8/8/6P1/7b/4K3 b - - 0 1", Color::Black),
+            ("4k3/8/8/8/1P6/b7/8/4K3 b - - 0 1", Color::Black),
+            ("4k3/8/8/8/6P1/7b/8/4K3 b - - 0 1", Color::Black),
+        ] {
+            let trapped = board(fen);
+
+            assert_eq!(
+                trapped_bishops(&trapped, colour),
+                -TRAPPED_BISHOP_PENALTY,
+                "{fen}"
+            );
+
+            // without the pawn shutting it in the bishop can get out
+            let mut freed = BoardBuilder::from(&trapped);
+            freed.clear_square(trapped.pieces(Piece::Pawn).to_square());
+
+            let freed = Board::try_from(&freed).unwrap();
+
+            assert_eq!(trapped_bishops(&freed, colour), Score::default(), "{fen}");
+        }
+
+        // the smaller penalty is for each of our own pawns on the bishop's colour
+        let blocked = board("4k3/8/8/8/3P4/2P1P3/8/2B1K3 w - - 0 1");
+
+        assert_eq!(
+            bad_bishops(&blocked, Color::White),
+            -(BAD_BISHOP_PAWN_PENALTY * 3)
+        );
     }
 }
@@ -0,0 +1,40 @@
+use chess::Piece;
+use search::SearchInformation;
+use uci::UciReport;
+
+pub mod chess960;
+pub mod epd;
+pub mod evaluation;
+pub mod nnue;
+pub mod perft;
+pub mod search;
+pub mod transposition;
+pub mod tune;
+pub mod uci;
+
+pub const INFINITY: i32 = 10000;
+pub const MAX_MINIMUM_THINKING_TIME: u64 = 5000;
+pub const DEFAULT_MOVE_OVERHEAD: u64 = 30;
+pub const MAX_MOVE_OVERHEAD: u64 = 5000;
+pub const MAX_CONTEMPT: i32 = 100;
+pub const DEFAULT_ASPIRATION_WINDOW: i32 = 25;
+pub const MAX_ASPIRATION_WINDOW: i32 = 500;
+pub const DEFAULT_ASPIRATION_GROWTH: i32 = 4;
+pub const MIN_ASPIRATION_GROWTH: i32 = 2; // anything less never gets the window open
+pub const MAX_ASPIRATION_GROWTH: i32 = 16;
+pub const MAX_PIECE_VALUE: i32 = 2000;
+
+// the options that override the midgame value of each piece
+pub const PIECE_VALUE_OPTIONS: [(&str, Piece); 5] = [
+    ("PawnValue", Piece::Pawn),
+    ("KnightValue", Piece::Knight),
+    ("BishopValue", Piece::Bishop),
+    ("RookValue", Piece::Rook),
+    ("QueenValue", Piece::Queen),
+];
+
+#[derive(Debug)]
+pub enum Information {
+    SearchInformation(SearchInformation),
+    UciInformation(UciReport),
+}
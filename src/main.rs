@@ -1,17 +1,26 @@
-use chess::Board;
-use search::{Search, SearchCommand, SearchInformation, SearchMode, SearchParams};
+use chess::{Board, ChessMove, Color};
+use kittycat::chess960::{is_legal, load_board, make_move, CastlingRooks};
+use kittycat::epd::{load_epd, EpdLimit, EpdRun};
+use kittycat::evaluation::{evaluate_verbose, load_eval_weights, white_score, EvalWeights};
+use kittycat::nnue::{is_network_file, load_network, Network};
+use kittycat::perft::perft_divide;
+use kittycat::search::{
+    halfmove_clock_after, HistoryEntry, Search, SearchCommand, SearchInformation, SearchMode,
+    SearchOptions, SearchParams,
+};
+use kittycat::transposition::{MAX_HASH_SIZE_MB, MIN_HASH_SIZE_MB};
+use kittycat::tune::tune;
+use kittycat::uci::{GameTime, SearchControl, Uci, UciControl, UciReport};
+use kittycat::{
+    Information, MAX_ASPIRATION_GROWTH, MAX_ASPIRATION_WINDOW, MAX_CONTEMPT,
+    MAX_MINIMUM_THINKING_TIME, MAX_MOVE_OVERHEAD, MAX_PIECE_VALUE, MIN_ASPIRATION_GROWTH,
+    PIECE_VALUE_OPTIONS,
+};
 use std::str::FromStr;
-use std::sync::{Arc, Mutex, RwLock};
-use std::time::Duration;
-use uci::{GameTime, Uci, UciControl, UciReport};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 use vampirc_uci::UciMessage;
 
-mod evaluation;
-mod search;
-mod uci;
-
-const INFINITY: i32 = 10000;
-
 fn main() {
     let mut engine = Engine::new();
 
@@ -20,9 +29,20 @@ fn main() {
 
 struct Engine {
     board: Arc<RwLock<Board>>,
+    history: Vec<HistoryEntry>, // the game so far, copied into each search
+    fullmove_number: u16,       // of the current position, which the board doesn't keep
+    castling_rooks: CastlingRooks, // castling rights in chess960, which the board doesn't keep
     search: Search,
     uci: Uci,
     info_rx: Option<crossbeam_channel::Receiver<Information>>,
+    search_options: SearchOptions,
+    eval_weights: Arc<EvalWeights>,
+    piece_values: [Option<i32>; 5], // set through the options, kept over any eval file
+    network: Option<Arc<Network>>,
+    time_used: Duration, // on our clock in this game, from timed searches only
+    clock_started: Option<Instant>, // when our clock started for the running search, if it's on it
+    chess960: bool,      // castling moves are sent as the king taking its own rook
+    epd_run: Option<EpdRun>, // test suite being run by the epd command
     debug: bool,
     quit: bool,
 }
@@ -31,9 +51,20 @@ impl Engine {
     fn new() -> Self {
         Self {
             board: Arc::new(RwLock::new(Board::default())),
+            history: Vec::new(),
+            fullmove_number: 1,
+            castling_rooks: CastlingRooks::default(),
             search: Search::new(),
             uci: Uci::new(),
             info_rx: None,
+            search_options: SearchOptions::default(),
+            eval_weights: Arc::new(EvalWeights::default()),
+            piece_values: [None; 5],
+            network: None,
+            time_used: Duration::default(),
+            clock_started: None,
+            chess960: false,
+            epd_run: None,
             debug: false,
             quit: false,
         }
@@ -46,10 +77,7 @@ impl Engine {
 
         self.uci.init(info_tx.clone());
 
-        let history = Arc::new(Mutex::new(Vec::new()));
-
-        self.search
-            .init(info_tx, Arc::clone(&self.board), Arc::clone(&history));
+        self.search.init(info_tx, Arc::clone(&self.board));
 
         while !self.quit {
             let information = self.info_rx.as_ref().unwrap().recv().unwrap();
@@ -58,57 +86,571 @@ impl Engine {
                 println!("{}", UciMessage::info_string(format!("{:?}", &information)));
             }
 
-            match information {
-                Information::UciInformation(uci_report) => match uci_report {
-                    UciReport::Uci => {
-                        self.uci.send(UciControl::Identify);
-                    }
-                    UciReport::Debug(debug) => self.debug = debug,
-                    UciReport::IsReady => self.uci.send(UciControl::Ready),
-                    UciReport::Position(fen, moves) => {
-                        let mut board = self.board.write().unwrap();
+            self.handle_information(information);
+        }
+    }
+
+    fn handle_information(&mut self, information: Information) {
+        match information {
+            Information::UciInformation(uci_report) => match uci_report {
+                UciReport::Uci => {
+                    self.uci.send(UciControl::Identify);
+                }
+                UciReport::Debug(debug) => self.debug = debug,
+                UciReport::IsReady => self.uci.send(UciControl::Ready),
+                UciReport::Position(fen, moves, unparsed) => {
+                    let root = if self.chess960 {
+                        load_board(&fen)
+                    } else {
+                        Board::from_str(&fen).map(|root| (root, CastlingRooks::default()))
+                    };
+
+                    // a bad fen leaves the previous position in place rather than some
+                    // half-built one
+                    let (root, castling_rooks) = match root {
+                        Ok(root) => root,
+                        Err(err) => {
+                            self.uci.send(UciControl::Info(format!(
+                                "ignoring position with fen {fen}: {err}"
+                            )));
+
+                            return;
+                        }
+                    };
+
+                    let mut board = self.board.write().unwrap();
+                    let history = &mut self.history;
+
+                    *board = root;
+
+                    self.castling_rooks = castling_rooks;
+
+                    // the game so far, so the search can see repetitions from before the root.
+                    // the fen's halfmove clock carries on into the moves played from it
+                    history.clear();
+                    history.push(HistoryEntry {
+                        hash: board.get_hash() ^ castling_rooks.hash(),
+                        halfmove_clock: fen
+                            .split_whitespace()
+                            .nth(4)
+                            .and_then(|clock| clock.parse().ok())
+                            .unwrap_or(0),
+                    });
+
+                    self.fullmove_number = fen
+                        .split_whitespace()
+                        .nth(5)
+                        .and_then(|number| number.parse().ok())
+                        .unwrap_or(1);
+
+                    // a move that didn't parse ends the list just like an illegal one
+                    let mut illegal = unparsed;
+
+                    for mov in moves {
+                        // the position up to the illegal move is kept, since everything
+                        // after it is meaningless
+                        if !is_legal(&board, &self.castling_rooks, mov) {
+                            illegal = Some(mov.to_string());
+
+                            break;
+                        }
+
+                        let new_board = make_move(&board, mov);
+                        let halfmove_clock = halfmove_clock_after(history, &board, mov);
+
+                        self.castling_rooks = self.castling_rooks.after(&board, mov);
 
-                        *board = Board::from_str(&fen).unwrap();
+                        history.push(HistoryEntry {
+                            hash: new_board.get_hash() ^ self.castling_rooks.hash(),
+                            halfmove_clock,
+                        });
 
-                        for mov in moves {
-                            *board = board.make_move_new(mov);
+                        if board.side_to_move() == Color::Black {
+                            self.fullmove_number += 1;
                         }
+
+                        *board = new_board;
                     }
-                    UciReport::UciNewGame => {
-                        *self.board.write().unwrap() = Board::default();
+
+                    if let Some(mov) = illegal {
+                        self.uci.send(UciControl::Info(format!(
+                            "illegal move {mov}, ignoring it and the moves after it"
+                        )));
                     }
-                    UciReport::Stop => self.search.send(SearchCommand::Stop),
-                    UciReport::Quit => self.quit(),
-                    UciReport::GoInfinite => self.search.send(SearchCommand::Start(SearchParams {
-                        search_mode: SearchMode::Infinite,
-                        move_time: Duration::default(),
-                        game_time: GameTime::default(),
-                    })),
-                    UciReport::GoMoveTime(move_time) => {
-                        self.search.send(SearchCommand::Start(SearchParams {
-                            search_mode: SearchMode::MoveTime,
-                            move_time: move_time - Duration::from_millis(50),
-                            game_time: GameTime::default(),
-                        }));
+                }
+                UciReport::SetOption(name, value) => self.set_option(&name, value),
+                UciReport::UciNewGame => {
+                    *self.board.write().unwrap() = Board::default();
+                    self.history.clear();
+                    self.fullmove_number = 1;
+                    self.castling_rooks = CastlingRooks::default();
+                    self.search.send(SearchCommand::ClearHash);
+                    self.time_used = Duration::default();
+                }
+                // the engine is free, so there's nothing to register
+                UciReport::Register => (),
+                UciReport::Stop => {
+                    // a stop abandons a test suite. the search it cuts short still sends its
+                    // best move, which has to be dropped rather than passed on to the gui
+                    if let Some(run) = &mut self.epd_run {
+                        run.stopped = true;
                     }
-                    UciReport::GoGameTime(game_time) => {
-                        self.search.send(SearchCommand::Start(SearchParams {
-                            search_mode: SearchMode::GameTime,
-                            move_time: Duration::default(),
+
+                    self.search.send(SearchCommand::Stop);
+                }
+                UciReport::PonderHit => {
+                    // the opponent played the expected move, so our clock is running from now
+                    self.clock_started = Some(Instant::now());
+
+                    self.search.send(SearchCommand::PonderHit);
+                }
+                UciReport::Quit => self.quit(),
+                UciReport::GoInfinite(search_control) => {
+                    self.clock_started = None;
+
+                    self.search.send(SearchCommand::Start(Box::new(
+                        self.search_params(SearchMode::Infinite, search_control),
+                    )));
+                }
+                UciReport::GoMoveTime(move_time, search_control) => {
+                    self.clock_started = None;
+
+                    self.search
+                        .send(SearchCommand::Start(Box::new(SearchParams {
+                            move_time: move_time.saturating_sub(self.search_options.move_overhead),
+                            ..self.search_params(SearchMode::MoveTime, search_control)
+                        })));
+                }
+                UciReport::GoPonder(game_time, search_control) => {
+                    // pondering is on the opponent's time
+                    self.clock_started = None;
+
+                    self.search
+                        .send(SearchCommand::Start(Box::new(SearchParams {
+                            game_time,
+                            ponder: true,
+                            ..self.search_params(SearchMode::GameTime, search_control)
+                        })));
+                }
+                UciReport::GoGameTime(game_time, search_control) => {
+                    self.clock_started = Some(Instant::now());
+
+                    self.search
+                        .send(SearchCommand::Start(Box::new(SearchParams {
                             game_time,
-                        }));
+                            ..self.search_params(SearchMode::GameTime, search_control)
+                        })));
+                }
+                UciReport::Display => {
+                    let board = *self.board.read().unwrap();
+
+                    self.uci.send(UciControl::Display {
+                        board,
+                        castling_rooks: self.chess960.then_some(self.castling_rooks),
+                        halfmove_clock: self.halfmove_clock(),
+                        fullmove_number: self.fullmove_number,
+                        time_used: self.time_used,
+                    });
+                }
+                UciReport::Epd(path, limit) => match load_epd(&path) {
+                    Ok(positions) => {
+                        self.epd_run = Some(EpdRun {
+                            positions,
+                            limit,
+                            current: 0,
+                            solved: 0,
+                            stopped: false,
+                        });
+
+                        self.search_epd_position();
                     }
-                    UciReport::Unknown => (),
+                    Err(err) => self
+                        .uci
+                        .send(UciControl::Info(format!("epd run failed: {err}"))),
                 },
-                Information::SearchInformation(search_info) => match search_info {
-                    SearchInformation::BestMove(bm) => self.uci.send(UciControl::BestMove(bm)),
-                    SearchInformation::Summary(summary) => {
-                        self.uci.send(UciControl::SearchSummary(summary));
+                UciReport::Eval => {
+                    let board = *self.board.read().unwrap();
+
+                    if let Some(network) = &self.network {
+                        let side_to_move =
+                            network.evaluate(&network.refresh(&board), board.side_to_move());
+
+                        let white = white_score(&board, side_to_move);
+
+                        self.uci.send(UciControl::Eval {
+                            white,
+                            side_to_move,
+                        });
+
+                        return;
                     }
-                    SearchInformation::ExtraInfo(info) => {
-                        self.uci.send(UciControl::Info(info));
+
+                    let breakdown =
+                        evaluate_verbose(&board, &self.eval_weights, self.halfmove_clock());
+
+                    let white = breakdown.white;
+                    let side_to_move = breakdown.side_to_move;
+
+                    self.uci.send(UciControl::EvalBreakdown(breakdown));
+
+                    self.uci.send(UciControl::Eval {
+                        white,
+                        side_to_move,
+                    });
+                }
+                UciReport::Perft(depth) => {
+                    // runs on this thread like tune, so deep counts hold up the engine
+                    let board = *self.board.read().unwrap();
+
+                    let start = Instant::now();
+
+                    let divide = perft_divide(&board, &self.castling_rooks, depth);
+
+                    let time = start.elapsed();
+
+                    // a depth of 0 has no moves to divide by, just the position itself
+                    let nodes = match depth {
+                        0 => 1,
+                        _ => divide.iter().map(|&(_, nodes)| nodes).sum(),
+                    };
+
+                    self.uci.send(UciControl::Perft {
+                        divide,
+                        nodes,
+                        time,
+                    });
+                }
+                UciReport::Tune(positions, output) => {
+                    // runs on this thread, so the engine doesn't respond until it's done
+                    let result = tune(&positions, &output, (*self.eval_weights).clone(), |info| {
+                        self.uci.send(UciControl::Info(info))
+                    });
+
+                    if let Err(err) = result {
+                        self.uci
+                            .send(UciControl::Info(format!("tuning failed: {err}")));
+                    }
+                }
+                UciReport::Unknown => (),
+            },
+            Information::SearchInformation(search_info) => match search_info {
+                SearchInformation::BestMove(bm, _) if self.epd_run.is_some() => {
+                    self.grade_epd_position(bm);
+                }
+                SearchInformation::BestMove(bm, ponder) => {
+                    self.uci.send(UciControl::BestMove(bm, ponder));
+                }
+                SearchInformation::SearchTime(time) => {
+                    // only the part of the search on our clock counts towards the total: all of
+                    // a timed search, or what's left of a ponder search after the ponderhit
+                    if let Some(clock_started) = self.clock_started.take() {
+                        self.time_used += time.min(clock_started.elapsed());
                     }
+
+                    if self.debug {
+                        self.uci.send(UciControl::Info(format!(
+                            "move time: {:?}, total time used: {:?}",
+                            time, self.time_used
+                        )));
+                    }
+                }
+                SearchInformation::Summary(summary) => {
+                    self.uci.send(UciControl::SearchSummary(summary));
+                }
+                SearchInformation::CurrMove(mov, number) => {
+                    self.uci.send(UciControl::CurrMove(mov, number));
+                }
+                SearchInformation::QuiescenceDepth(qdepth) => {
+                    if self.debug {
+                        self.uci
+                            .send(UciControl::Info(format!("max quiescence depth: {qdepth}")));
+                    }
+                }
+                SearchInformation::AspirationResearches(researches) => {
+                    if self.debug {
+                        self.uci.send(UciControl::Info(format!(
+                            "aspiration re-searches: {researches}"
+                        )));
+                    }
+                }
+                SearchInformation::PawnHashHitRate(hit_rate) => {
+                    if self.debug {
+                        self.uci.send(UciControl::Info(format!(
+                            "pawn hash hit rate: {:.1}%",
+                            hit_rate * 100.0
+                        )));
+                    }
+                }
+                SearchInformation::ExtraInfo(info) => {
+                    self.uci.send(UciControl::Info(info));
+                }
+            },
+        }
+    }
+
+    fn set_option(&mut self, name: &str, value: Option<String>) {
+        match name {
+            "Quiescence" => match value.as_deref() {
+                Some("true") => self.search_options.quiescence = true,
+                Some("false") => self.search_options.quiescence = false,
+                _ => self.uci.send(UciControl::Info(format!(
+                    "invalid value for option {name}: {value:?}"
+                ))),
+            },
+            "Minimum Thinking Time" => match value.as_deref().map(str::parse::<u64>) {
+                Some(Ok(ms)) => {
+                    self.search_options.minimum_thinking_time =
+                        Duration::from_millis(ms.min(MAX_MINIMUM_THINKING_TIME))
+                }
+                _ => self.uci.send(UciControl::Info(format!(
+                    "invalid value for option {name}: {value:?}"
+                ))),
+            },
+            "Move Overhead" => match value.as_deref().map(str::parse::<u64>) {
+                Some(Ok(ms)) => {
+                    self.search_options.move_overhead =
+                        Duration::from_millis(ms.min(MAX_MOVE_OVERHEAD))
+                }
+                _ => self.uci.send(UciControl::Info(format!(
+                    "invalid value for option {name}: {value:?}"
+                ))),
+            },
+            "Ponder" => match value.as_deref() {
+                Some("true") => self.search_options.ponder = true,
+                Some("false") => self.search_options.ponder = false,
+                _ => self.uci.send(UciControl::Info(format!(
+                    "invalid value for option {name}: {value:?}"
+                ))),
+            },
+            "UCI_Chess960" => match value.as_deref() {
+                Some("true") => self.chess960 = true,
+                Some("false") => self.chess960 = false,
+                _ => self.uci.send(UciControl::Info(format!(
+                    "invalid value for option {name}: {value:?}"
+                ))),
+            },
+            "Contempt" => match value.as_deref().map(str::parse::<i32>) {
+                Some(Ok(contempt)) => {
+                    self.search_options.contempt = contempt.clamp(-MAX_CONTEMPT, MAX_CONTEMPT)
+                }
+                _ => self.uci.send(UciControl::Info(format!(
+                    "invalid value for option {name}: {value:?}"
+                ))),
+            },
+            "Aspiration Window" => match value.as_deref().map(str::parse::<i32>) {
+                Some(Ok(window)) => {
+                    self.search_options.aspiration_window = window.clamp(1, MAX_ASPIRATION_WINDOW)
+                }
+                _ => self.uci.send(UciControl::Info(format!(
+                    "invalid value for option {name}: {value:?}"
+                ))),
+            },
+            "Aspiration Growth" => match value.as_deref().map(str::parse::<i32>) {
+                Some(Ok(growth)) => {
+                    self.search_options.aspiration_growth =
+                        growth.clamp(MIN_ASPIRATION_GROWTH, MAX_ASPIRATION_GROWTH)
+                }
+                _ => self.uci.send(UciControl::Info(format!(
+                    "invalid value for option {name}: {value:?}"
+                ))),
+            },
+            "PawnValue" | "KnightValue" | "BishopValue" | "RookValue" | "QueenValue" => {
+                let index = PIECE_VALUE_OPTIONS
+                    .iter()
+                    .position(|(option, _)| *option == name)
+                    .unwrap();
+
+                match value.as_deref().map(str::parse::<i32>) {
+                    Some(Ok(piece_value)) => {
+                        self.piece_values[index] = Some(piece_value.clamp(0, MAX_PIECE_VALUE));
+
+                        self.apply_piece_values();
+                    }
+                    _ => self.uci.send(UciControl::Info(format!(
+                        "invalid value for option {name}: {value:?}"
+                    ))),
+                }
+            }
+            "EvalFile" => self.set_eval_file(value.as_deref()),
+            "Clear Hash" => self.search.send(SearchCommand::ClearHash),
+            "Hash" => match value.as_deref().map(str::parse::<usize>) {
+                Some(Ok(size_mb)) => self.search.send(SearchCommand::ResizeHash(
+                    size_mb.clamp(MIN_HASH_SIZE_MB, MAX_HASH_SIZE_MB),
+                )),
+                _ => self.uci.send(UciControl::Info(format!(
+                    "invalid value for option {name}: {value:?}"
+                ))),
+            },
+            _ => self
+                .uci
+                .send(UciControl::Info(format!("unknown option: {name}"))),
+        }
+    }
+
+    // the parameters for a search in the given mode, with the limits of the other modes unset.
+    // the search control applies on top of any mode
+    fn search_params(
+        &self,
+        search_mode: SearchMode,
+        search_control: SearchControl,
+    ) -> SearchParams {
+        SearchParams {
+            search_mode,
+            move_time: Duration::default(),
+            game_time: GameTime::default(),
+            depth: search_control.depth.map(|depth| depth.max(1)),
+            search_moves: self.legal_search_moves(search_control.search_moves),
+            history: self.history.clone(),
+            castling_rooks: self.castling_rooks,
+            ponder: false,
+            options: self.search_options,
+            eval_weights: Arc::clone(&self.eval_weights),
+            network: self.network.clone(),
+        }
+    }
+
+    // drops any moves that aren't legal in the current position. if none are left, the whole
+    // position is searched rather than having no move to play
+    fn legal_search_moves(&self, search_moves: Vec<ChessMove>) -> Vec<ChessMove> {
+        let board = *self.board.read().unwrap();
+
+        search_moves
+            .into_iter()
+            .filter(|&mov| is_legal(&board, &self.castling_rooks, mov))
+            .collect()
+    }
+
+    // sets up the test suite's current position and searches it, as a position and go command
+    // from the gui would
+    fn search_epd_position(&mut self) {
+        let run = self.epd_run.as_ref().unwrap();
+
+        let board = run.positions[run.current].board;
+
+        self.clock_started = None;
+
+        *self.board.write().unwrap() = board;
+
+        self.castling_rooks = CastlingRooks::default();
+
+        self.history.clear();
+        self.history.push(HistoryEntry {
+            hash: board.get_hash(),
+            halfmove_clock: 0,
+        });
+
+        self.fullmove_number = 1;
+
+        // every position starts from an empty hash, so results don't depend on the suite's order
+        self.search.send(SearchCommand::ClearHash);
+
+        let search_params = match run.limit {
+            EpdLimit::Depth(depth) => self.search_params(
+                SearchMode::Infinite,
+                SearchControl {
+                    depth: Some(depth),
+                    search_moves: Vec::new(),
                 },
+            ),
+            EpdLimit::MoveTime(move_time) => SearchParams {
+                move_time,
+                ..self.search_params(SearchMode::MoveTime, SearchControl::default())
+            },
+        };
+
+        self.search
+            .send(SearchCommand::Start(Box::new(search_params)));
+    }
+
+    // reports whether the move played solves the current position, then moves on to the next one
+    // or the final score
+    fn grade_epd_position(&mut self, best_move: Option<ChessMove>) {
+        let run = self.epd_run.as_mut().unwrap();
+
+        if run.stopped {
+            self.uci.send(UciControl::Info(format!(
+                "epd run stopped, solved {} of {} positions searched",
+                run.solved, run.current
+            )));
+
+            self.epd_run = None;
+
+            return;
+        }
+
+        let position = &run.positions[run.current];
+
+        // a position with no legal moves can only have an am operation, and nothing was played
+        let solved = best_move.is_some_and(|best_move| position.solved_by(best_move));
+
+        run.solved += usize::from(solved);
+        run.current += 1;
+
+        self.uci.send(UciControl::Info(format!(
+            "{} {}: played {}",
+            if solved { "pass" } else { "fail" },
+            position.id,
+            best_move.map_or("nothing".to_string(), |best_move| best_move.to_string())
+        )));
+
+        if run.current < run.positions.len() {
+            self.search_epd_position();
+
+            return;
+        }
+
+        self.uci.send(UciControl::Info(format!(
+            "solved {} of {} positions",
+            run.solved,
+            run.positions.len()
+        )));
+
+        self.epd_run = None;
+    }
+
+    // the eval scales towards a draw as the fifty-move rule nears, so d and eval need the clock
+    // of the current position, which is kept in the history rather than on the board
+    fn halfmove_clock(&self) -> u16 {
+        self.history.last().map_or(0, |entry| entry.halfmove_clock)
+    }
+
+    // a network file replaces the evaluation, anything else is read as weights for the built-in
+    // one. either way, on failure the engine goes back to the built-in weights. piece values set
+    // through the options apply on top of whichever weights end up in use, since the gui may
+    // send the options in any order
+    fn set_eval_file(&mut self, path: Option<&str>) {
+        self.eval_weights = Arc::new(EvalWeights::default());
+        self.network = None;
+
+        let message = match path {
+            None | Some("") | Some("<empty>") => {
+                "using the built-in evaluation weights".to_string()
+            }
+            Some(path) => {
+                let loaded = if is_network_file(path) {
+                    load_network(path).map(|network| self.network = Some(Arc::new(network)))
+                } else {
+                    load_eval_weights(path).map(|weights| self.eval_weights = Arc::new(weights))
+                };
+
+                match loaded {
+                    Ok(()) => format!("loaded evaluation from {path}"),
+                    Err(err) => format!(
+                        "failed to load evaluation from {path}: {err}, using the built-in weights"
+                    ),
+                }
+            }
+        };
+
+        self.apply_piece_values();
+
+        self.uci.send(UciControl::Info(message));
+    }
+
+    fn apply_piece_values(&mut self) {
+        for (&(_, piece), piece_value) in PIECE_VALUE_OPTIONS.iter().zip(self.piece_values) {
+            if let Some(piece_value) = piece_value {
+                Arc::make_mut(&mut self.eval_weights).set_piece_value(piece, piece_value);
             }
         }
     }
@@ -117,11 +659,223 @@ impl Engine {
         self.uci.send(UciControl::Quit);
         self.search.send(SearchCommand::Quit);
         self.quit = true;
+
+        self.search.wait();
+        self.uci.wait();
     }
 }
 
-#[derive(Debug)]
-pub enum Information {
-    SearchInformation(SearchInformation),
-    UciInformation(UciReport),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::Piece;
+    use kittycat::epd::EpdPosition;
+    use std::fs;
+    use std::thread;
+
+    fn set_option(engine: &mut Engine, name: &str, value: &str) {
+        engine.set_option(name, Some(value.to_string()));
+    }
+
+    #[test]
+    fn piece_value_options_survive_loading_an_eval_file() {
+        let default_knight = EvalWeights::default().piece_value(Piece::Knight);
+
+        let path =
+            std::env::temp_dir().join(format!("kittycat-{}-weights.txt", std::process::id()));
+
+        fs::write(&path, "value knight 400 400\n").unwrap();
+
+        let mut engine = Engine::new();
+
+        set_option(&mut engine, "PawnValue", "150");
+        set_option(&mut engine, "EvalFile", path.to_str().unwrap());
+
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(engine.eval_weights.piece_value(Piece::Pawn), 150);
+        assert_eq!(engine.eval_weights.piece_value(Piece::Knight), 400);
+
+        // going back to the built-in weights keeps the override too
+        set_option(&mut engine, "EvalFile", "<empty>");
+
+        assert_eq!(engine.eval_weights.piece_value(Piece::Pawn), 150);
+        assert_eq!(
+            engine.eval_weights.piece_value(Piece::Knight),
+            default_knight
+        );
+    }
+
+    #[test]
+    fn failed_eval_file_load_keeps_piece_value_overrides() {
+        let path = std::env::temp_dir().join(format!("kittycat-{}-bad.txt", std::process::id()));
+
+        fs::write(&path, "value knight four hundred\n").unwrap();
+
+        let mut engine = Engine::new();
+
+        set_option(&mut engine, "RookValue", "550");
+        set_option(&mut engine, "EvalFile", path.to_str().unwrap());
+
+        fs::remove_file(&path).unwrap();
+
+        // back on the built-in weights, with the override on top
+        let mut expected = EvalWeights::default();
+        expected.set_piece_value(Piece::Rook, 550);
+
+        assert_eq!(engine.eval_weights.to_string(), expected.to_string());
+
+        // a file that isn't there fails the same way
+        set_option(&mut engine, "EvalFile", "/nonexistent/kittycat-weights.txt");
+
+        assert_eq!(engine.eval_weights.to_string(), expected.to_string());
+    }
+
+    #[test]
+    fn stopping_an_epd_run_drops_the_best_move_of_the_search_it_cut_short() {
+        let (output_tx, output_rx) = crossbeam_channel::unbounded();
+
+        let mut engine = Engine::new();
+        engine.uci = Uci::with_output(output_tx);
+
+        let position = || EpdPosition {
+            id: "start".to_string(),
+            board: Board::default(),
+            best_moves: vec![ChessMove::from_str("e2e4").unwrap()],
+            avoid_moves: Vec::new(),
+        };
+
+        engine.epd_run = Some(EpdRun {
+            positions: vec![position(), position()],
+            limit: EpdLimit::Depth(1),
+            current: 0,
+            solved: 0,
+            stopped: false,
+        });
+
+        let best_move = SearchInformation::BestMove(ChessMove::from_str("e2e4").ok(), None);
+
+        engine.handle_information(Information::UciInformation(UciReport::Stop));
+        engine.handle_information(Information::SearchInformation(best_move));
+
+        assert!(engine.epd_run.is_none());
+
+        let output = output_rx.try_iter().collect::<Vec<_>>();
+
+        assert!(!output
+            .iter()
+            .any(|control| matches!(control, UciControl::BestMove(..))));
+
+        assert!(output.iter().any(|control| matches!(
+            control,
+            UciControl::Info(info) if info.starts_with("epd run stopped")
+        )));
+
+        // with nothing left to drop, the next best move is the gui's again
+        let best_move = SearchInformation::BestMove(ChessMove::from_str("e2e4").ok(), None);
+
+        engine.handle_information(Information::SearchInformation(best_move));
+
+        assert!(output_rx
+            .try_iter()
+            .any(|control| matches!(control, UciControl::BestMove(..))));
+    }
+
+    #[test]
+    fn moves_before_a_bad_one_are_played_and_it_is_reported() {
+        let (output_tx, output_rx) = crossbeam_channel::unbounded();
+
+        let mut engine = Engine::new();
+        engine.uci = Uci::with_output(output_tx);
+
+        let e2e4 = ChessMove::from_str("e2e4").unwrap();
+
+        for (moves, bad_move) in [
+            // doesn't parse as a move at all
+            (vec![e2e4], Some("e7".to_string())),
+            // parses, but isn't legal after e2e4
+            (vec![e2e4, ChessMove::from_str("e2e4").unwrap()], None),
+        ] {
+            let position = UciReport::Position(Board::default().to_string(), moves, bad_move);
+
+            engine.handle_information(Information::UciInformation(position));
+
+            assert_eq!(
+                *engine.board.read().unwrap(),
+                Board::default().make_move_new(e2e4)
+            );
+            assert_eq!(engine.history.len(), 2);
+
+            assert!(output_rx.try_iter().any(|control| matches!(
+                control,
+                UciControl::Info(info) if info.starts_with("illegal move")
+            )));
+        }
+    }
+
+    #[test]
+    fn only_time_on_our_clock_counts_towards_the_total() {
+        let mut engine = Engine::new();
+
+        let report = |engine: &mut Engine, report| {
+            engine.handle_information(Information::UciInformation(report));
+        };
+
+        let search_time = |engine: &mut Engine, time| {
+            engine.handle_information(Information::SearchInformation(
+                SearchInformation::SearchTime(time),
+            ));
+        };
+
+        let search_control = SearchControl::default();
+
+        // analysis and fixed time searches aren't on the game clock
+        report(&mut engine, UciReport::GoInfinite(search_control.clone()));
+        search_time(&mut engine, Duration::from_secs(5));
+
+        report(
+            &mut engine,
+            UciReport::GoMoveTime(Duration::from_secs(5), search_control.clone()),
+        );
+        search_time(&mut engine, Duration::from_secs(5));
+
+        assert_eq!(engine.time_used, Duration::ZERO);
+
+        // a timed search counts in full
+        report(
+            &mut engine,
+            UciReport::GoGameTime(GameTime::default(), search_control.clone()),
+        );
+        thread::sleep(Duration::from_millis(20));
+        search_time(&mut engine, Duration::from_millis(10));
+
+        assert_eq!(engine.time_used, Duration::from_millis(10));
+
+        // a ponder search only from the ponderhit, and not at all if it's stopped instead
+        let ponder_time = Duration::from_millis(200);
+
+        report(
+            &mut engine,
+            UciReport::GoPonder(GameTime::default(), search_control.clone()),
+        );
+        search_time(&mut engine, ponder_time);
+
+        assert_eq!(engine.time_used, Duration::from_millis(10));
+
+        report(
+            &mut engine,
+            UciReport::GoPonder(GameTime::default(), search_control),
+        );
+        report(&mut engine, UciReport::PonderHit);
+        thread::sleep(Duration::from_millis(20));
+        search_time(&mut engine, ponder_time);
+
+        assert!(engine.time_used >= Duration::from_millis(30));
+        assert!(engine.time_used < Duration::from_millis(10) + ponder_time);
+
+        // and a new game starts from nothing
+        report(&mut engine, UciReport::UciNewGame);
+
+        assert_eq!(engine.time_used, Duration::ZERO);
+    }
 }
@@ -0,0 +1,267 @@
+use crate::chess960::castling;
+use crate::evaluation::MAX_EVAL;
+use chess::{Board, ChessMove, Color, Piece, Square};
+use std::fs;
+
+// a halfkp network: every non-king piece is a feature relative to the position of each side's
+// own king, so each side has its own first layer accumulator, which is cheap to update when a
+// move changes a few pieces and only needs rebuilding when that side's king moves. the two
+// accumulators, side to move first, go through a clipped relu into a single output
+//
+// the file is little-endian: the magic bytes, the hidden layer size as a u32, the feature
+// weights as i16 (feature-major), the hidden biases as i16, the output weights as i16 (side to
+// move half first) and the output bias as an i32
+const MAGIC: &[u8; 4] = b"KCNN";
+
+const FEATURES: usize = 64 * 640; // own king square * piece kind and colour * square
+const MAX_HIDDEN: usize = 4096;
+
+const QA: i64 = 255; // quantisation of the hidden layer, also the clipped relu ceiling
+const QB: i64 = 64; // quantisation of the output weights
+const EVAL_SCALE: i64 = 400; // centipawns per unit of network output
+
+pub struct Network {
+    hidden: usize,             // size of each accumulator
+    feature_weights: Vec<i16>, // FEATURES * hidden
+    feature_biases: Vec<i16>,  // hidden
+    output_weights: Vec<i16>,  // 2 * hidden
+    output_bias: i32,          // scaled by QA * QB
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Accumulator {
+    values: [Vec<i16>; 2], // by the colour whose point of view it is
+}
+
+// the pieces a move takes off and puts on the board, kings included
+struct MoveChanges {
+    removed: [Option<(Piece, Color, Square)>; 2],
+    added: [Option<(Piece, Color, Square)>; 2],
+}
+
+pub fn is_network_file(path: &str) -> bool {
+    fs::read(path).is_ok_and(|bytes| bytes.starts_with(MAGIC))
+}
+
+pub fn load_network(path: &str) -> Result<Network, String> {
+    let bytes = fs::read(path).map_err(|err| err.to_string())?;
+
+    let mut reader = bytes
+        .strip_prefix(MAGIC)
+        .ok_or_else(|| "not a network file".to_string())?;
+
+    let hidden = read_u32(&mut reader)? as usize;
+
+    if hidden == 0 || hidden > MAX_HIDDEN {
+        return Err(format!("unsupported hidden layer size {hidden}"));
+    }
+
+    let network = Network {
+        hidden,
+        feature_weights: read_i16s(&mut reader, FEATURES * hidden)?,
+        feature_biases: read_i16s(&mut reader, hidden)?,
+        output_weights: read_i16s(&mut reader, 2 * hidden)?,
+        output_bias: read_u32(&mut reader)? as i32,
+    };
+
+    if !reader.is_empty() {
+        return Err(format!("{} unexpected bytes at the end", reader.len()));
+    }
+
+    Ok(network)
+}
+
+fn read_u32(reader: &mut &[u8]) -> Result<u32, String> {
+    let (bytes, rest) = reader
+        .split_first_chunk::<4>()
+        .ok_or_else(|| "file is too short".to_string())?;
+
+    *reader = rest;
+
+    Ok(u32::from_le_bytes(*bytes))
+}
+
+fn read_i16s(reader: &mut &[u8], count: usize) -> Result<Vec<i16>, String> {
+    if reader.len() < count * 2 {
+        return Err("file is too short".to_string());
+    }
+
+    let (bytes, rest) = reader.split_at(count * 2);
+
+    *reader = rest;
+
+    Ok(bytes
+        .chunks_exact(2)
+        .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+        .collect())
+}
+
+impl Network {
+    // builds both accumulators from scratch
+    pub fn refresh(&self, board: &Board) -> Accumulator {
+        let mut accumulator = Accumulator {
+            values: [Vec::new(), Vec::new()],
+        };
+
+        for perspective in [Color::White, Color::Black] {
+            self.refresh_perspective(board, perspective, &mut accumulator);
+        }
+
+        accumulator
+    }
+
+    fn refresh_perspective(
+        &self,
+        board: &Board,
+        perspective: Color,
+        accumulator: &mut Accumulator,
+    ) {
+        let values = &mut accumulator.values[perspective.to_index()];
+
+        values.clear();
+        values.extend_from_slice(&self.feature_biases);
+
+        let king = board.king_square(perspective);
+
+        for square in *board.combined() & !board.pieces(Piece::King) {
+            let piece = board.piece_on(square).unwrap(); // the square is occupied
+            let colour = board.color_on(square).unwrap();
+
+            let feature = feature_index(perspective, king, piece, colour, square);
+
+            self.add_feature(values, feature);
+        }
+    }
+
+    // fills in the accumulator of the position after a move from the one before it. only the
+    // pieces the move touches change, except for the side whose king moved, which has every
+    // feature change and is rebuilt from the new position instead
+    pub fn update(
+        &self,
+        board: &Board,
+        mov: ChessMove,
+        new_board: &Board,
+        parent: &Accumulator,
+        child: &mut Accumulator,
+    ) {
+        let changes = move_changes(board, mov);
+
+        let moved_king = board.piece_on(mov.get_source()) == Some(Piece::King);
+
+        for perspective in [Color::White, Color::Black] {
+            if moved_king && perspective == board.side_to_move() {
+                self.refresh_perspective(new_board, perspective, child);
+                continue;
+            }
+
+            let values = &mut child.values[perspective.to_index()];
+
+            values.clone_from(&parent.values[perspective.to_index()]);
+
+            let king = new_board.king_square(perspective);
+
+            for (piece, colour, square) in changes.removed.into_iter().flatten() {
+                if piece != Piece::King {
+                    let feature = feature_index(perspective, king, piece, colour, square);
+
+                    self.remove_feature(values, feature);
+                }
+            }
+
+            for (piece, colour, square) in changes.added.into_iter().flatten() {
+                if piece != Piece::King {
+                    let feature = feature_index(perspective, king, piece, colour, square);
+
+                    self.add_feature(values, feature);
+                }
+            }
+        }
+    }
+
+    // the score for the side to move, in centipawns
+    pub fn evaluate(&self, accumulator: &Accumulator, side_to_move: Color) -> i32 {
+        let us = &accumulator.values[side_to_move.to_index()];
+        let them = &accumulator.values[(!side_to_move).to_index()];
+
+        let (our_weights, their_weights) = self.output_weights.split_at(self.hidden);
+
+        let layer = |values: &[i16], weights: &[i16]| {
+            values
+                .iter()
+                .zip(weights)
+                .map(|(&value, &weight)| (value as i64).clamp(0, QA) * weight as i64)
+                .sum::<i64>()
+        };
+
+        let output = self.output_bias as i64 + layer(us, our_weights) + layer(them, their_weights);
+
+        (output * EVAL_SCALE / (QA * QB)).clamp(-MAX_EVAL as i64, MAX_EVAL as i64) as i32
+    }
+
+    fn add_feature(&self, values: &mut [i16], feature: usize) {
+        let weights = &self.feature_weights[feature * self.hidden..(feature + 1) * self.hidden];
+
+        for (value, &weight) in values.iter_mut().zip(weights) {
+            *value = value.wrapping_add(weight);
+        }
+    }
+
+    fn remove_feature(&self, values: &mut [i16], feature: usize) {
+        let weights = &self.feature_weights[feature * self.hidden..(feature + 1) * self.hidden];
+
+        for (value, &weight) in values.iter_mut().zip(weights) {
+            *value = value.wrapping_sub(weight);
+        }
+    }
+}
+
+// black sees the board flipped, so both sides share the same weights
+fn feature_index(
+    perspective: Color,
+    king: Square,
+    piece: Piece,
+    colour: Color,
+    square: Square,
+) -> usize {
+    let orient = |square: Square| match perspective {
+        Color::White => square.to_index(),
+        Color::Black => square.to_index() ^ 56,
+    };
+
+    let kind = piece.to_index() * 2 + usize::from(colour != perspective);
+
+    orient(king) * 640 + kind * 64 + orient(square)
+}
+
+fn move_changes(board: &Board, mov: ChessMove) -> MoveChanges {
+    let colour = board.side_to_move();
+
+    let source = mov.get_source();
+    let dest = mov.get_dest();
+
+    let piece = board.piece_on(source).unwrap(); // the move is legal, so there's a piece to move
+
+    let mut changes = MoveChanges {
+        removed: [Some((piece, colour, source)), None],
+        added: [
+            Some((mov.get_promotion().unwrap_or(piece), colour, dest)),
+            None,
+        ],
+    };
+
+    if let Some(captured) = board.piece_on(dest) {
+        changes.removed[1] = Some((captured, !colour, dest));
+    } else if piece == Piece::Pawn && source.get_file() != dest.get_file() {
+        // en passant, the captured pawn is beside the source rather than on the destination
+        let captured_square = Square::make_square(source.get_rank(), dest.get_file());
+
+        changes.removed[1] = Some((Piece::Pawn, !colour, captured_square));
+    }
+
+    if let Some(castle) = castling(board, mov) {
+        changes.removed[1] = Some((Piece::Rook, colour, castle.rook.0));
+        changes.added[1] = Some((Piece::Rook, colour, castle.rook.1));
+    }
+
+    changes
+}
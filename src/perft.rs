@@ -0,0 +1,198 @@
+use crate::chess960::{self, castling_moves, CastlingRooks};
+use chess::{Board, ChessMove, MoveGen};
+
+// the number of leaf nodes of the legal move tree to the given depth, to check the move generator
+// against known counts. chess960 castling is generated separately from the board's moves
+pub fn perft(board: &Board, castling_rooks: &CastlingRooks, depth: u8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let moves = MoveGen::new_legal(board);
+    let castles = castling_moves(board, castling_rooks);
+
+    // the leaves are just the legal moves, so they don't need to be made
+    if depth == 1 {
+        return (moves.len() + castles.len()) as u64;
+    }
+
+    moves
+        .chain(castles)
+        .map(|mov| child_perft(board, castling_rooks, mov, depth))
+        .sum()
+}
+
+// the perft count below each root move, to narrow a wrong total down to the moves it comes from
+pub fn perft_divide(
+    board: &Board,
+    castling_rooks: &CastlingRooks,
+    depth: u8,
+) -> Vec<(ChessMove, u64)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+
+    MoveGen::new_legal(board)
+        .chain(castling_moves(board, castling_rooks))
+        .map(|mov| (mov, child_perft(board, castling_rooks, mov, depth)))
+        .collect()
+}
+
+fn child_perft(board: &Board, castling_rooks: &CastlingRooks, mov: ChessMove, depth: u8) -> u64 {
+    perft(
+        &chess960::make_move(board, mov),
+        &castling_rooks.after(board, mov),
+        depth - 1,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    // a few positions from the standard perft suite, between them covering castling, en passant,
+    // promotions, discovered checks and pins, at depths quick enough for every test run. the whole
+    // suite is run by full_perft_suite, which reads it from the file in KITTYCAT_PERFT_SUITE:
+    //   KITTYCAT_PERFT_SUITE=perftsuite.epd cargo test --release full_perft_suite -- --ignored
+    const PERFT_SUITE: &str = "\
+        rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - ;D1 20 ;D2 400 ;D3 8902
+        r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - ;D1 48 ;D2 2039
+        4k3/8/8/8/8/8/8/4K2R w K - ;D1 15 ;D2 66 ;D3 1197 ;D4 7059
+        r3k2r/8/8/8/8/8/8/R3K2R w KQkq - ;D1 26 ;D2 568 ;D3 13744
+        8/1n4N1/2k5/8/8/5K2/1N4n1/8 w - - ;D1 14 ;D2 195 ;D3 2760
+        8/P1k5/K7/8/8/8/8/8 w - - ;D1 6 ;D2 27 ;D3 273 ;D4 1329
+        8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 ;D1 15 ;D2 126 ;D3 1928 ;D4 13931
+        r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1 ;D1 6 ;D2 264 ;D3 9467
+        8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - ;D1 14 ;D2 191 ;D3 2812
+    ";
+
+    struct PerftPosition {
+        fen: String,
+        counts: Vec<(u8, u64)>, // depth and the expected count at it
+    }
+
+    // each line is a fen, with or without its move counters, followed by the expected counts as
+    //   4k3/8/8/8/8/8/8/4K2R w K - ;D1 15 ;D2 66 ;D3 1197
+    fn parse_perft_epd(contents: &str) -> Result<Vec<PerftPosition>, String> {
+        let mut positions = Vec::new();
+
+        for (line_number, line) in contents.lines().enumerate() {
+            let line_number = line_number + 1;
+
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.split(';');
+
+            let fen = fields.next().unwrap().trim();
+
+            // the fen parser wants the move counters, which the suite often leaves out
+            let fen = match fen.split_whitespace().count() {
+                4 => format!("{fen} 0 1"),
+                _ => fen.to_string(),
+            };
+
+            let counts = fields
+                .map(|field| {
+                    let (depth, count) = field
+                        .trim()
+                        .strip_prefix('D')
+                        .and_then(|field| field.split_once(' '))
+                        .ok_or_else(|| format!("line {line_number}: invalid count {field}"))?;
+
+                    match (depth.parse(), count.trim().parse()) {
+                        (Ok(depth), Ok(count)) => Ok((depth, count)),
+                        _ => Err(format!("line {line_number}: invalid count {field}")),
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            positions.push(PerftPosition { fen, counts });
+        }
+
+        Ok(positions)
+    }
+
+    fn check_perft_suite(contents: &str) {
+        for position in parse_perft_epd(contents).unwrap() {
+            let board = Board::from_str(&position.fen).unwrap();
+
+            for (depth, count) in position.counts {
+                assert_eq!(
+                    perft(&board, &CastlingRooks::default(), depth),
+                    count,
+                    "{} depth {depth}",
+                    position.fen
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn matches_start_position_and_kiwipete_counts() {
+        let start = Board::default();
+
+        let kiwipete =
+            Board::from_str("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+
+        let no_castling = CastlingRooks::default();
+
+        for (depth, count) in (1..).zip([20, 400, 8902, 197281]) {
+            assert_eq!(perft(&start, &no_castling, depth), count);
+        }
+
+        for (depth, count) in (1..).zip([48, 2039, 97862]) {
+            assert_eq!(perft(&kiwipete, &no_castling, depth), count);
+        }
+
+        // the divide adds up to the same total, with one entry per legal move
+        let divide = perft_divide(&kiwipete, &no_castling, 3);
+
+        assert_eq!(divide.len(), 48);
+        assert_eq!(divide.iter().map(|&(_, nodes)| nodes).sum::<u64>(), 97862);
+
+        assert_eq!(perft(&start, &no_castling, 0), 1);
+        assert!(perft_divide(&start, &no_castling, 0).is_empty());
+    }
+
+    #[test]
+    fn perft_suite() {
+        check_perft_suite(PERFT_SUITE);
+    }
+
+    #[test]
+    #[ignore]
+    fn full_perft_suite() {
+        let path = std::env::var("KITTYCAT_PERFT_SUITE")
+            .expect("KITTYCAT_PERFT_SUITE should be the path of the suite to run");
+
+        check_perft_suite(&std::fs::read_to_string(path).unwrap());
+    }
+
+    #[test]
+    fn reads_counts_for_each_depth() {
+        let positions = parse_perft_epd(
+            "# a comment\n\
+             4k3/8/8/8/8/8/8/4K2R w K - ;D1 15 ;D2 66\n\
+             \n\
+             8/8/8/8/8/8/6k1/4K2R w K - 0 1 ;D3 564\n",
+        )
+        .unwrap();
+
+        assert_eq!(positions.len(), 2);
+
+        assert_eq!(positions[0].fen, "4k3/8/8/8/8/8/8/4K2R w K - 0 1");
+        assert_eq!(positions[0].counts, vec![(1, 15), (2, 66)]);
+
+        assert_eq!(positions[1].fen, "8/8/8/8/8/8/6k1/4K2R w K - 0 1");
+        assert_eq!(positions[1].counts, vec![(3, 564)]);
+
+        assert!(parse_perft_epd("4k3/8/8/8/8/8/8/4K2R w K - ;D1 x\n").is_err());
+        assert!(parse_perft_epd("4k3/8/8/8/8/8/8/4K2R w K - ;15\n").is_err());
+    }
+}
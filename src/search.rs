@@ -1,19 +1,61 @@
-use crate::evaluation::evaluate_position;
+use crate::chess960::{self, castling_moves, CastlingRooks};
+use crate::evaluation::{
+    evaluate_position, material_and_psqt, material_and_psqt_after, EvalWeights, PawnHashTable,
+    Score, LIGHT_SQUARES,
+};
+use crate::nnue::{Accumulator, Network};
+use crate::transposition::{Bound, TranspositionTable, TtEntry, DEFAULT_HASH_SIZE_MB};
 use crate::uci::GameTime;
-use crate::{Information, INFINITY};
-use chess::{BitBoard, Board, ChessMove, Color, MoveGen, Piece, EMPTY};
+use crate::{
+    Information, DEFAULT_ASPIRATION_GROWTH, DEFAULT_ASPIRATION_WINDOW, DEFAULT_MOVE_OVERHEAD,
+    INFINITY,
+};
+use chess::{
+    get_bishop_moves, get_king_moves, get_knight_moves, get_pawn_attacks, get_rook_moves, BitBoard,
+    Board, ChessMove, Color, MoveGen, Piece, Square, EMPTY,
+};
 use crossbeam_channel::{Receiver, Sender};
-use std::sync::{Arc, Mutex, RwLock};
+use std::collections::VecDeque;
+use std::sync::{Arc, OnceLock, RwLock};
 use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
 
 const MAX_PLY: u8 = 200;
+const MISSING_CLOCK_TIME: Duration = Duration::from_secs(1);
+const CURRMOVE_REPORT_DELAY: Duration = Duration::from_secs(1);
+const MAX_HISTORY: i32 = 16384;
+const NULL_MOVE_MIN_DEPTH: u8 = 3;
+const LMR_MIN_DEPTH: u8 = 3;
+const LMP_MAX_DEPTH: u8 = 3;
+const RFP_MAX_DEPTH: u8 = 6;
+const RFP_MARGIN: i32 = 100;
+const FUTILITY_MARGIN: i32 = 175;
+const RAZOR_MAX_DEPTH: u8 = 3;
+const RAZOR_MARGIN: i32 = 250;
+const PROBCUT_MIN_DEPTH: u8 = 5;
+const PROBCUT_REDUCTION: u8 = 4;
+const PROBCUT_MARGIN: i32 = 200; // how far above beta a capture has to score to prune the node
+const SINGULAR_MIN_DEPTH: u8 = 6;
+const SINGULAR_MARGIN: i32 = 2; // per ply of depth below the tt score the other moves must stay
+const MAX_SINGULAR_EXTENSIONS: u8 = 6; // per line, so repeated extensions can't run away
+const IIR_MIN_DEPTH: u8 = 4;
+const ASPIRATION_MIN_DEPTH: u8 = 4;
+const ASPIRATION_MAX_WINDOW: i32 = 1000; // past this the bound is opened up completely
+const BRANCHING_FACTOR: f64 = 2.0; // how much longer each iteration takes than the last
+const LMR_FULL_DEPTH_MOVES: u16 = 4;
+const NULL_MOVE_EVAL_MARGIN: i32 = 200;
 
 pub struct Search {
     handle: Option<JoinHandle<()>>,
     control_tx: Option<Sender<SearchCommand>>,
 }
 
+impl Default for Search {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Search {
     pub fn new() -> Self {
         Self {
@@ -22,57 +64,94 @@ impl Search {
         }
     }
 
-    pub fn init(
-        &mut self,
-        info_tx: Sender<Information>,
-        board: Arc<RwLock<Board>>,
-        history: Arc<Mutex<Vec<HistoryEntry>>>,
-    ) {
+    pub fn init(&mut self, info_tx: Sender<Information>, board: Arc<RwLock<Board>>) {
         let (control_tx, control_rx) = crossbeam_channel::unbounded::<SearchCommand>();
 
         let h = thread::spawn(move || {
             let mut search_params = None;
 
+            // owned by the search thread rather than SearchState so it survives between searches
+            let mut tt = TranspositionTable::new(DEFAULT_HASH_SIZE_MB);
+
+            // hash commands that arrived during the last search, handled before any new ones
+            let mut deferred = VecDeque::new();
+
             let mut quit = false;
             let mut halt = true;
 
             while !quit {
-                let cmd = control_rx.recv().unwrap();
+                let cmd = deferred
+                    .pop_front()
+                    .unwrap_or_else(|| control_rx.recv().unwrap());
 
                 match cmd {
                     SearchCommand::Start(sp) => {
-                        search_params = Some(sp);
+                        search_params = Some(*sp);
                         halt = false;
+
+                        tt.new_search();
                     }
                     SearchCommand::Stop => halt = true,
                     SearchCommand::Quit => quit = true,
-                    SearchCommand::Nothing => (),
+                    SearchCommand::ResizeHash(size_mb) => {
+                        tt = TranspositionTable::new(size_mb);
+
+                        let info = SearchInformation::ExtraInfo(format!(
+                            "hash table resized to {} entries ({} KB)",
+                            tt.num_entries(),
+                            tt.size_kb()
+                        ));
+                        info_tx.send(Information::SearchInformation(info)).unwrap();
+                    }
+                    SearchCommand::ClearHash => tt.clear(),
+                    SearchCommand::PonderHit | SearchCommand::Nothing => (),
                 }
 
                 if !halt && !quit {
+                    // the search works on its own copy of the root position, making and
+                    // unmaking moves by copying boards, so the shared one is only read here
+                    let root_board = *board.read().unwrap();
+
+                    // the history is copied too, so the engine is free to set up the next
+                    // position while this search is still running
+                    let mut history = search_params.as_ref().unwrap().history.clone();
+
+                    let mut search_state = SearchState::new();
+
                     let mut refs = SearchRefs {
-                        board: Arc::clone(&board),
+                        board: root_board,
+                        castling_rooks: search_params.as_ref().unwrap().castling_rooks,
+                        psqt: material_and_psqt(
+                            &root_board,
+                            &search_params.as_ref().unwrap().eval_weights,
+                        ),
                         search_params: search_params.as_ref().unwrap(),
-                        search_state: &mut SearchState::new(),
+                        search_state: &mut search_state,
                         control_rx: &control_rx,
                         report_tx: &info_tx,
-                        history: &mut history.lock().unwrap(),
+                        history: &mut history,
+                        tt: &mut tt,
                     };
 
-                    let (best_move, terminate) = Self::iterative_deepening(&mut refs);
+                    if let Some(network) = &refs.search_params.network {
+                        refs.search_state.accumulators =
+                            vec![network.refresh(&root_board); MAX_PLY as usize + 1];
+                    }
+
+                    let (best_move, ponder_move, terminate) = Self::iterative_deepening(&mut refs);
 
-                    let info = SearchInformation::BestMove(best_move);
+                    let ponder_move = ponder_move.filter(|_| refs.search_params.options.ponder);
+
+                    let info = SearchInformation::BestMove(best_move, ponder_move);
                     info_tx.send(Information::SearchInformation(info)).unwrap();
 
-                    match terminate {
-                        SearchTerminate::Stop => {
-                            halt = true;
-                        }
-                        SearchTerminate::Quit => {
-                            halt = true;
-                            quit = true;
-                        }
-                        SearchTerminate::Nothing => (),
+                    deferred.append(&mut search_state.deferred);
+
+                    // a finished search must not be restarted by the next unrelated command
+                    halt = true;
+
+                    if terminate == SearchTerminate::Quit {
+                        quit = true;
                     }
                 }
             }
@@ -88,21 +167,80 @@ impl Search {
         }
     }
 
-    fn iterative_deepening(refs: &mut SearchRefs) -> (ChessMove, SearchTerminate) {
+    pub fn wait(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.join().unwrap();
+        }
+    }
+
+    // searches the position to a fixed depth on the calling thread, with a table of its own, and
+    // returns the best move with the summary of the deepest iteration
+    pub fn run_to_depth(board: Board, depth: u8) -> (Option<ChessMove>, Option<SearchSummary>) {
+        let params = depth_params(&board, depth);
+
+        // the control channel is kept open, since the search treats it closing as a quit
+        let (_control_tx, control_rx) = crossbeam_channel::unbounded();
+        let (report_tx, report_rx) = crossbeam_channel::unbounded();
+
+        let mut tt = TranspositionTable::new(DEFAULT_HASH_SIZE_MB);
+        let mut search_state = SearchState::new();
+        let mut history = params.history.clone();
+
+        let mut refs = SearchRefs {
+            board,
+            castling_rooks: params.castling_rooks,
+            psqt: material_and_psqt(&board, &params.eval_weights),
+            search_params: &params,
+            search_state: &mut search_state,
+            control_rx: &control_rx,
+            report_tx: &report_tx,
+            history: &mut history,
+            tt: &mut tt,
+        };
+
+        let (best_move, _, _) = Self::iterative_deepening(&mut refs);
+
+        let summary = report_rx
+            .try_iter()
+            .filter_map(|info| match info {
+                Information::SearchInformation(SearchInformation::Summary(summary)) => {
+                    Some(summary)
+                }
+                _ => None,
+            })
+            .last();
+
+        (best_move, summary)
+    }
+
+    fn iterative_deepening(
+        refs: &mut SearchRefs,
+    ) -> (Option<ChessMove>, Option<ChessMove>, SearchTerminate) {
+        let search_start = Instant::now();
+
         let mut depth = 1;
-        let mut best_move = None;
+
+        // a search stopped before it finishes the first iteration still has a move to play, as
+        // long as the root has any legal moves at all
+        let search_moves = &refs.search_params.search_moves;
+
+        let mut best_move = MoveGen::new_legal(&refs.board)
+            .chain(castling_moves(&refs.board, &refs.castling_rooks))
+            .find(|mov| search_moves.is_empty() || search_moves.contains(mov));
+
+        let mut ponder_move = None;
         let mut root_pv = Vec::new();
         let mut stop = false;
 
         if refs.search_params.search_mode == SearchMode::GameTime {
             let game_time = &refs.search_params.game_time;
 
-            let is_white = refs.board.read().unwrap().side_to_move() == chess::Color::White;
+            let is_white = refs.board.side_to_move() == chess::Color::White;
 
             let clock = if is_white {
-                game_time.wtime.unwrap()
+                game_time.wtime
             } else {
-                game_time.btime.unwrap()
+                game_time.btime
             };
 
             let increment = if is_white {
@@ -111,22 +249,41 @@ impl Search {
                 game_time.binc.unwrap_or(Duration::from_millis(0))
             };
 
-            let base_time = match game_time.mtg {
-                Some(mtg) => {
-                    if mtg == 0 {
-                        clock
-                    } else {
-                        clock / mtg as u32
-                    }
-                }
-                None => clock / 10,
-            };
+            refs.search_state.allocated_time = match clock {
+                Some(clock) => {
+                    let base_time = match game_time.mtg {
+                        Some(mtg) => {
+                            if mtg == 0 {
+                                clock
+                            } else {
+                                clock / mtg as u32
+                            }
+                        }
+                        None => clock / 10,
+                    };
 
-            let time_slice = base_time + increment - Duration::from_millis(100);
+                    // the gui and the connection to it take some of our time too
+                    let time_slice = (base_time + increment)
+                        .saturating_sub(refs.search_params.options.move_overhead);
 
-            let factor = 0.4;
+                    let factor = 0.4;
 
-            refs.search_state.allocated_time = time_slice.mul_f64(factor);
+                    time_slice.mul_f64(factor)
+                }
+                None => {
+                    refs.report_tx
+                        .send(Information::SearchInformation(
+                            SearchInformation::ExtraInfo(format!(
+                                "no clock given for the side to move, searching for {:?}",
+                                MISSING_CLOCK_TIME
+                            )),
+                        ))
+                        .unwrap();
+
+                    MISSING_CLOCK_TIME
+                }
+            }
+            .max(refs.search_params.options.minimum_thinking_time);
 
             refs.report_tx
                 .send(Information::SearchInformation(
@@ -138,19 +295,83 @@ impl Search {
                 .unwrap();
         }
 
-        let alpha = -INFINITY;
-        let beta = INFINITY;
+        let mut eval = 0;
+        let mut researches = 0u32;
+
+        refs.search_state.root_history_len = refs.history.len();
 
         refs.search_state.start_time = Some(Instant::now());
 
-        while (depth <= MAX_PLY) && !stop {
+        refs.search_state.pondering = refs.search_params.ponder;
+
+        // checkmate or stalemate is as certain after one iteration as after any number of them
+        let max_depth = match best_move {
+            Some(_) => refs
+                .search_params
+                .depth
+                .map_or(MAX_PLY, |depth| depth.min(MAX_PLY)),
+            None => 1,
+        };
+
+        while (depth <= max_depth) && !stop {
             refs.search_state.depth = depth;
 
-            let eval = Self::negamax(refs, &mut root_pv, depth, alpha, beta);
+            let iteration_start = Instant::now();
+
+            // once the previous iteration gives a reasonable estimate, search a narrow window
+            // around it, which cuts off far more. a score outside the window is only a bound,
+            // so the side it fell out of is widened and the depth searched again. the widening
+            // grows with every fail on that side, since a score that moved once tends to keep
+            // moving, until the side is opened up completely
+            let window = refs.search_params.options.aspiration_window;
+            let growth = refs.search_params.options.aspiration_growth;
+
+            let mut alpha_delta = window;
+            let mut beta_delta = window;
+
+            let (mut alpha, mut beta) = if depth >= ASPIRATION_MIN_DEPTH {
+                (
+                    (eval - alpha_delta).max(-INFINITY),
+                    (eval + beta_delta).min(INFINITY),
+                )
+            } else {
+                (-INFINITY, INFINITY)
+            };
+
+            eval = loop {
+                let score = Self::negamax(refs, &mut root_pv, depth, alpha, beta, None);
+
+                if refs.search_state.terminate != SearchTerminate::Nothing {
+                    break score;
+                }
+
+                if score <= alpha && alpha > -INFINITY {
+                    alpha_delta *= growth;
+
+                    alpha = if alpha_delta > ASPIRATION_MAX_WINDOW {
+                        -INFINITY
+                    } else {
+                        (eval - alpha_delta).max(-INFINITY)
+                    };
+                } else if score >= beta && beta < INFINITY {
+                    beta_delta *= growth;
+
+                    beta = if beta_delta > ASPIRATION_MAX_WINDOW {
+                        INFINITY
+                    } else {
+                        (eval + beta_delta).min(INFINITY)
+                    };
+                } else {
+                    break score;
+                }
+
+                researches += 1;
+            };
 
             if refs.search_state.terminate == SearchTerminate::Nothing {
                 if !root_pv.is_empty() {
                     best_move = Some(root_pv[0]);
+                    ponder_move = root_pv.get(1).copied();
                 }
 
                 let elapsed = refs.search_state.start_time.unwrap().elapsed();
@@ -167,6 +388,12 @@ impl Search {
 
                 let info = SearchInformation::Summary(summary);
 
+                refs.report_tx
+                    .send(Information::SearchInformation(info))
+                    .unwrap();
+
+                let info = SearchInformation::QuiescenceDepth(refs.search_state.max_qdepth);
+
                 refs.report_tx
                     .send(Information::SearchInformation(info))
                     .unwrap();
@@ -174,8 +401,16 @@ impl Search {
                 depth += 1;
             }
 
-            let time_up = if refs.search_params.search_mode == SearchMode::GameTime {
-                refs.search_state.start_time.unwrap().elapsed() > refs.search_state.allocated_time
+            // each iteration takes a roughly constant factor longer than the last, so don't
+            // start one that is projected to run past the allocated time, since an unfinished
+            // iteration is wasted
+            let time_up = if refs.search_params.search_mode == SearchMode::GameTime
+                && !refs.search_state.pondering
+            {
+                let projected_finish = refs.search_state.start_time.unwrap().elapsed()
+                    + iteration_start.elapsed().mul_f64(BRANCHING_FACTOR);
+
+                projected_finish > refs.search_state.allocated_time
             } else {
                 false
             };
@@ -185,7 +420,34 @@ impl Search {
             }
         }
 
-        (best_move.unwrap(), refs.search_state.terminate)
+        refs.report_tx
+            .send(Information::SearchInformation(
+                SearchInformation::AspirationResearches(researches),
+            ))
+            .unwrap();
+
+        refs.report_tx
+            .send(Information::SearchInformation(
+                SearchInformation::PawnHashHitRate(refs.search_state.pawn_table.hit_rate()),
+            ))
+            .unwrap();
+
+        if refs.search_state.terminate == SearchTerminate::Nothing {
+            wait_minimum_thinking_time(refs);
+        }
+
+        if refs.search_state.pondering {
+            wait_for_ponder_end(refs);
+        }
+
+        // everything from the go command to the best move, waits included
+        refs.report_tx
+            .send(Information::SearchInformation(
+                SearchInformation::SearchTime(search_start.elapsed()),
+            ))
+            .unwrap();
+
+        (best_move, ponder_move, refs.search_state.terminate)
     }
 
     fn negamax(
@@ -193,7 +455,8 @@ impl Search {
         pv: &mut Vec<ChessMove>,
         mut depth: u8,
         mut alpha: i32,
-        beta: i32,
+        mut beta: i32,
+        prev_move: Option<ChessMove>,
     ) -> i32 {
         let mut do_pvs = false;
 
@@ -206,41 +469,266 @@ impl Search {
         }
 
         if refs.search_state.ply >= MAX_PLY {
-            return evaluate_position(&refs.board.read().unwrap());
+            return evaluate(refs);
+        }
+
+        // set while checking whether the tt move is singular, in which case this node is a
+        // search of every other move and its result says nothing about the position itself
+        let excluded = refs.search_state.excluded[refs.search_state.ply as usize];
+
+        // mate distance pruning: even mating right here can't beat a mate already found
+        // closer to the root, and being mated here can't be worse than one found there
+        if refs.search_state.ply > 0 {
+            alpha = alpha.max(-INFINITY + refs.search_state.ply as i32);
+            beta = beta.min(INFINITY - refs.search_state.ply as i32);
+
+            if alpha >= beta {
+                return alpha;
+            }
         }
 
-        let is_check = *refs.board.read().unwrap().checkers() != EMPTY;
+        let is_check = *refs.board.checkers() != EMPTY;
 
         if is_check {
             depth += 1;
         }
 
         if depth == 0 {
+            if !refs.search_params.options.quiescence {
+                return evaluate(refs);
+            }
+
+            refs.search_state.qsearch_root_ply = refs.search_state.ply;
+
             return Search::quiescence(alpha, beta, pv, refs);
         }
 
+        let hash = position_hash(refs);
+
+        let tt_entry = refs.tt.probe(hash, refs.search_state.ply);
+
+        if let Some(entry) = tt_entry {
+            if refs.search_state.ply > 0 && excluded.is_none() {
+                if let Some(score) = entry.cutoff(depth, alpha, beta) {
+                    return score;
+                }
+            }
+        }
+
+        // internal iterative reduction: without a move to try first, ordering is poor, so
+        // search a ply shallower and let that fill the table with a best move for next time
+        if depth >= IIR_MIN_DEPTH
+            && excluded.is_none()
+            && pv.is_empty()
+            && tt_entry.and_then(|entry| entry.best_move).is_none()
+        {
+            depth -= 1;
+        }
+
+        let is_pv = beta - alpha > 1;
+
+        // the static eval is meaningless when in check, since the side to move can't stand pat
+        let static_eval = if is_check { None } else { Some(evaluate(refs)) };
+
+        // reverse futility pruning: far enough above beta near the horizon, no reasonable
+        // sequence of moves is going to bring the score back down. never done against a mate
+        // bound, where the static eval says nothing about the outcome
+        if let Some(static_eval) = static_eval {
+            if !is_pv
+                && depth <= RFP_MAX_DEPTH
+                && beta.abs() < INFINITY / 2
+                && static_eval - RFP_MARGIN * depth as i32 >= beta
+            {
+                return static_eval;
+            }
+        }
+
+        // razoring: this far below alpha near the horizon, only tactics can save the node. if
+        // the captures don't get back to alpha either, the full-width search is skipped
+        if let Some(static_eval) = static_eval {
+            if !is_pv
+                && refs.search_params.options.quiescence
+                && depth <= RAZOR_MAX_DEPTH
+                && alpha.abs() < INFINITY / 2
+                && static_eval + RAZOR_MARGIN * (depth as i32) < alpha
+            {
+                refs.search_state.qsearch_root_ply = refs.search_state.ply;
+
+                let score = Search::quiescence(alpha, beta, &mut Vec::new(), refs);
+
+                if score <= alpha {
+                    return score;
+                }
+            }
+        }
+
+        // the previous move being a null move (or the root) is signalled by prev_move being
+        // None, which also stops two null moves from being made in a row
+        if let Some(static_eval) = static_eval {
+            if !is_pv
+                && prev_move.is_some()
+                && excluded.is_none()
+                && depth >= NULL_MOVE_MIN_DEPTH
+                && static_eval >= beta
+                && has_non_pawn_material(&refs.board)
+            {
+                let reduction = null_move_reduction(depth, static_eval, beta);
+
+                if Self::null_move_fails_high(refs, depth, reduction, beta) {
+                    return beta;
+                }
+            }
+        }
+
+        // probcut: if a capture beats beta by a wide margin in a much shallower search, the
+        // full search would almost certainly beat beta too
+        if let Some(static_eval) = static_eval {
+            if !is_pv
+                && excluded.is_none()
+                && depth >= PROBCUT_MIN_DEPTH
+                && beta.abs() < INFINITY / 2
+            {
+                if let Some(score) = Self::probcut(refs, depth, beta, static_eval) {
+                    return score;
+                }
+            }
+        }
+
         refs.search_state.nodes += 1;
 
         let mut best_eval_score = -INFINITY - 1;
 
         let mut legal_moves_found = 0;
 
-        let moves_ordered = move_ordering(refs, pv.get(0).copied());
+        let mut best_move = None;
+
+        let tt_move = tt_entry.and_then(|entry| entry.best_move);
+
+        let countermove = prev_move
+            .and_then(|prev_move| countermove_slot(refs, prev_move))
+            .and_then(|slot| *slot);
+
+        let moves_ordered = move_ordering(refs, pv.first().copied(), tt_move, countermove);
+
+        // futility pruning: at the frontier a quiet move can't gain enough to lift a static
+        // eval this far below alpha
+        let futility_pruning = !is_pv
+            && depth == 1
+            && static_eval.is_some_and(|static_eval| static_eval + FUTILITY_MARGIN <= alpha);
+
+        // a tt move that scored well at close to this depth, with every alternative falling
+        // clearly short of it, is the critical move here and gets searched one ply deeper
+        let singular_extension = match tt_entry {
+            Some(entry) => {
+                refs.search_state.ply > 0
+                    && excluded.is_none()
+                    && depth >= SINGULAR_MIN_DEPTH
+                    && entry.bound != Bound::Upper
+                    && entry.depth + 3 >= depth
+                    && entry.score.abs() < INFINITY / 2
+                    && refs.search_state.extensions < MAX_SINGULAR_EXTENSIONS
+                    && Self::is_singular(refs, entry, depth, prev_move)
+            }
+            None => false,
+        };
+
+        let search_moves = &refs.search_params.search_moves;
 
         for legal in moves_ordered {
-            let old_pos = *refs.board.read().unwrap();
+            if Some(legal) == excluded {
+                continue;
+            }
+
+            // go searchmoves restricts the root to the moves given, the tree below is searched
+            // as normal
+            if refs.search_state.ply == 0
+                && !search_moves.is_empty()
+                && !search_moves.contains(&legal)
+            {
+                continue;
+            }
+
+            let old_pos = refs.board;
+            let old_castling_rooks = refs.castling_rooks;
+
+            let new_pos = chess960::make_move(&old_pos, legal);
+
+            // near the horizon, once enough moves have been tried without us being in trouble,
+            // the remaining quiet moves are very unlikely to matter
+            let late_move_pruning = !is_pv
+                && !is_check
+                && depth <= LMP_MAX_DEPTH
+                && legal_moves_found > 3 + depth as u16 * depth as u16
+                && best_eval_score > -INFINITY / 2;
+
+            // captures, promotions and checks can still change the picture, so only quiet
+            // moves are ever pruned
+            if (late_move_pruning || futility_pruning)
+                && is_quiet(&old_pos, legal)
+                && *new_pos.checkers() == EMPTY
+            {
+                // still a legal move, so the node can't be mistaken for mate or stalemate
+                legal_moves_found += 1;
+
+                continue;
+            }
+
+            let old_psqt = refs.psqt;
 
-            let new_move = refs.board.read().unwrap().make_move_new(legal);
+            refs.board = new_pos;
 
-            *refs.board.write().unwrap() = new_move;
+            refs.castling_rooks = old_castling_rooks.after(&old_pos, legal);
+
+            refs.psqt = material_and_psqt_after(
+                &old_pos,
+                legal,
+                old_psqt,
+                &refs.search_params.eval_weights,
+            );
+
+            update_accumulator(refs, &old_pos, Some(legal));
+
+            let halfmove_clock = halfmove_clock_after(refs.history, &old_pos, legal);
 
             refs.history.push(HistoryEntry {
-                hash: refs.board.read().unwrap().get_hash(),
-                is_reversible_move: !(old_pos.piece_on(legal.get_source()) == Some(Piece::Pawn)
-                    || old_pos.piece_on(legal.get_dest()).is_some()),
+                hash: position_hash(refs),
+                halfmove_clock,
             });
 
             legal_moves_found += 1;
+
+            if refs.search_state.ply == 0 {
+                refs.search_state.root_move_number = legal_moves_found;
+
+                report_root_progress(refs, legal);
+            }
+
+            // late quiet moves are unlikely to be best, so they get a cheaper reduced search
+            // first. killers and the tt move are ordered early because they are expected to be
+            // good, and moves that give check are too forcing to reduce
+            let reduction = if depth >= LMR_MIN_DEPTH
+                && legal_moves_found > LMR_FULL_DEPTH_MOVES
+                && !is_check
+                && *new_pos.checkers() == EMPTY
+                && is_quiet(&old_pos, legal)
+                && Some(legal) != tt_move
+                && !refs.search_state.killers[refs.search_state.ply as usize].contains(&Some(legal))
+            {
+                late_move_reduction(depth, legal_moves_found).min(depth - 2)
+            } else {
+                0
+            };
+
+            let extension = if singular_extension && Some(legal) == tt_move {
+                1
+            } else {
+                0
+            };
+
+            let new_depth = depth - 1 + extension;
+
+            refs.search_state.extensions += extension;
+
             refs.search_state.ply += 1;
 
             if refs.search_state.ply > refs.search_state.seldepth {
@@ -249,23 +737,65 @@ impl Search {
 
             let mut node_pv = Vec::new();
 
-            let mut eval_score = 0;
+            let mut eval_score = -draw_score(refs);
 
             if !is_draw(refs) {
-                if do_pvs {
-                    eval_score = -Self::negamax(refs, &mut node_pv, depth - 1, -alpha - 1, -alpha);
+                if reduction > 0 {
+                    eval_score = -Self::negamax(
+                        refs,
+                        &mut node_pv,
+                        new_depth - reduction,
+                        -alpha - 1,
+                        -alpha,
+                        Some(legal),
+                    );
+                }
 
-                    if (eval_score > alpha) && (eval_score < beta) {
-                        eval_score = -Self::negamax(refs, &mut node_pv, depth - 1, -beta, -alpha);
+                // a reduced search that fails low confirms the move is bad, anything else
+                // has to be verified at full depth
+                if reduction == 0 || eval_score > alpha {
+                    if do_pvs {
+                        eval_score = -Self::negamax(
+                            refs,
+                            &mut node_pv,
+                            new_depth,
+                            -alpha - 1,
+                            -alpha,
+                            Some(legal),
+                        );
+
+                        if (eval_score > alpha) && (eval_score < beta) {
+                            eval_score = -Self::negamax(
+                                refs,
+                                &mut node_pv,
+                                new_depth,
+                                -beta,
+                                -alpha,
+                                Some(legal),
+                            );
+                        }
+                    } else {
+                        eval_score = -Self::negamax(
+                            refs,
+                            &mut node_pv,
+                            new_depth,
+                            -beta,
+                            -alpha,
+                            Some(legal),
+                        );
                     }
-                } else {
-                    eval_score = -Self::negamax(refs, &mut node_pv, depth - 1, -beta, -alpha);
                 }
             }
 
             refs.search_state.ply -= 1;
 
-            *refs.board.write().unwrap() = old_pos;
+            refs.search_state.extensions -= extension;
+
+            refs.board = old_pos;
+
+            refs.castling_rooks = old_castling_rooks;
+
+            refs.psqt = old_psqt;
 
             refs.history.pop();
 
@@ -274,6 +804,33 @@ impl Search {
             }
 
             if eval_score >= beta {
+                if is_quiet(&old_pos, legal) {
+                    store_killer(refs, legal);
+
+                    let side = old_pos.side_to_move().to_index();
+                    let entry = &mut refs.search_state.butterfly[side]
+                        [legal.get_source().to_index()][legal.get_dest().to_index()];
+
+                    update_history(entry, depth as i32 * depth as i32);
+
+                    if let Some(slot) =
+                        prev_move.and_then(|prev_move| countermove_slot(refs, prev_move))
+                    {
+                        *slot = Some(legal);
+                    }
+                }
+
+                if refs.search_state.terminate == SearchTerminate::Nothing && excluded.is_none() {
+                    refs.tt.store(
+                        hash,
+                        depth,
+                        beta,
+                        Some(legal),
+                        Bound::Lower,
+                        refs.search_state.ply,
+                    );
+                }
+
                 return beta;
             }
 
@@ -282,6 +839,8 @@ impl Search {
 
                 do_pvs = true;
 
+                best_move = Some(legal);
+
                 pv.clear();
                 pv.push(legal);
                 pv.append(&mut node_pv);
@@ -289,16 +848,196 @@ impl Search {
         }
 
         if legal_moves_found == 0 {
+            // the excluded move was the only one, so the alternatives can't beat anything
+            if excluded.is_some() {
+                return alpha;
+            }
+
             if is_check {
                 return -INFINITY + refs.search_state.ply as i32;
             }
 
-            return 0;
+            return draw_score(refs);
+        }
+
+        if refs.search_state.terminate == SearchTerminate::Nothing && excluded.is_none() {
+            // a node that raised alpha without failing high has an exact score, otherwise
+            // every move failed low and alpha is only an upper bound
+            let bound = if best_move.is_some() {
+                Bound::Exact
+            } else {
+                Bound::Upper
+            };
+
+            refs.tt
+                .store(hash, depth, alpha, best_move, bound, refs.search_state.ply);
         }
 
         alpha
     }
 
+    // searches every move except the tt move at reduced depth, against a window just below the
+    // tt score. if none of them gets there, the tt move is singular
+    fn is_singular(
+        refs: &mut SearchRefs,
+        entry: TtEntry,
+        depth: u8,
+        prev_move: Option<ChessMove>,
+    ) -> bool {
+        let tt_move = match entry.best_move {
+            Some(tt_move) if chess960::is_legal(&refs.board, &refs.castling_rooks, tt_move) => {
+                tt_move
+            }
+            _ => return false,
+        };
+
+        let singular_beta = entry.score - SINGULAR_MARGIN * depth as i32;
+
+        let ply = refs.search_state.ply as usize;
+
+        refs.search_state.excluded[ply] = Some(tt_move);
+
+        let score = Self::negamax(
+            refs,
+            &mut Vec::new(),
+            (depth - 1) / 2,
+            singular_beta - 1,
+            singular_beta,
+            prev_move,
+        );
+
+        refs.search_state.excluded[ply] = None;
+
+        score < singular_beta && refs.search_state.terminate == SearchTerminate::Nothing
+    }
+
+    // gives the opponent a free move and searches the result at reduced depth. if we still
+    // beat beta the position is so good that searching it properly is a waste of time
+    fn null_move_fails_high(refs: &mut SearchRefs, depth: u8, reduction: u8, beta: i32) -> bool {
+        let old_pos = refs.board;
+
+        let null_pos = match old_pos.null_move() {
+            Some(null_pos) => null_pos,
+            None => return false,
+        };
+
+        refs.board = null_pos;
+
+        update_accumulator(refs, &old_pos, None);
+
+        // treated as irreversible, so repetition detection doesn't look past the null move
+        refs.history.push(HistoryEntry {
+            hash: position_hash(refs),
+            halfmove_clock: 0,
+        });
+
+        refs.search_state.ply += 1;
+
+        let score = -Self::negamax(
+            refs,
+            &mut Vec::new(),
+            (depth - 1).saturating_sub(reduction),
+            -beta,
+            -beta + 1,
+            None,
+        );
+
+        refs.search_state.ply -= 1;
+
+        refs.history.pop();
+
+        refs.board = old_pos;
+
+        score >= beta && refs.search_state.terminate == SearchTerminate::Nothing
+    }
+
+    fn probcut(refs: &mut SearchRefs, depth: u8, beta: i32, static_eval: i32) -> Option<i32> {
+        let probcut_beta = beta + PROBCUT_MARGIN;
+
+        let old_pos = refs.board;
+        let old_castling_rooks = refs.castling_rooks;
+
+        for legal in MoveGen::new_legal(&old_pos) {
+            // only captures and promotions can swing the score by the margin, and only those
+            // that win enough material on the exchange to plausibly do so are worth trying
+            if is_quiet(&old_pos, legal)
+                || see(&old_pos, legal, &refs.search_params.eval_weights)
+                    < probcut_beta - static_eval
+            {
+                continue;
+            }
+
+            let new_pos = old_pos.make_move_new(legal);
+
+            let old_psqt = refs.psqt;
+
+            refs.board = new_pos;
+
+            // a capture can take a rook that could still castle
+            refs.castling_rooks = old_castling_rooks.after(&old_pos, legal);
+
+            refs.psqt = material_and_psqt_after(
+                &old_pos,
+                legal,
+                old_psqt,
+                &refs.search_params.eval_weights,
+            );
+
+            update_accumulator(refs, &old_pos, Some(legal));
+
+            refs.history.push(HistoryEntry {
+                hash: position_hash(refs),
+                halfmove_clock: 0,
+            });
+
+            refs.search_state.ply += 1;
+
+            let mut score = -draw_score(refs);
+
+            if !is_draw(refs) {
+                // a quiescence search first cheaply weeds out captures that don't hold up
+                score = if refs.search_params.options.quiescence {
+                    refs.search_state.qsearch_root_ply = refs.search_state.ply;
+
+                    -Self::quiescence(-probcut_beta, -probcut_beta + 1, &mut Vec::new(), refs)
+                } else {
+                    probcut_beta
+                };
+
+                if score >= probcut_beta {
+                    score = -Self::negamax(
+                        refs,
+                        &mut Vec::new(),
+                        depth - PROBCUT_REDUCTION,
+                        -probcut_beta,
+                        -probcut_beta + 1,
+                        Some(legal),
+                    );
+                }
+            }
+
+            refs.search_state.ply -= 1;
+
+            refs.history.pop();
+
+            refs.board = old_pos;
+
+            refs.castling_rooks = old_castling_rooks;
+
+            refs.psqt = old_psqt;
+
+            if refs.search_state.terminate != SearchTerminate::Nothing {
+                return None;
+            }
+
+            if score >= probcut_beta {
+                return Some(score);
+            }
+        }
+
+        None
+    }
+
     fn quiescence(
         mut alpha: i32,
         beta: i32,
@@ -316,34 +1055,71 @@ impl Search {
         }
 
         if refs.search_state.ply >= MAX_PLY {
-            return evaluate_position(&refs.board.read().unwrap());
+            return evaluate(refs);
+        }
+
+        let board = refs.board;
+
+        let is_check = *board.checkers() != EMPTY;
+
+        // standing pat is only possible when not in check. in check the side to move has to
+        // respond, and the static eval can hide that every response gets it mated
+        if !is_check {
+            let eval_score = evaluate(refs);
+
+            if eval_score >= beta {
+                return beta;
+            }
+
+            if eval_score > alpha {
+                alpha = eval_score;
+            }
         }
 
-        let eval_score = evaluate_position(&refs.board.read().unwrap());
+        let mut legal_moves = MoveGen::new_legal(&board);
 
-        if eval_score >= beta {
-            return beta;
+        // every evasion has to be tried when in check, the only legal ones may be quiet
+        if !is_check {
+            let targets = board.color_combined(!board.side_to_move());
+            legal_moves.set_iterator_mask(*targets);
         }
 
-        if eval_score > alpha {
-            alpha = eval_score;
+        let mut moves = (&mut legal_moves).collect::<Vec<_>>();
+
+        if is_check && moves.is_empty() {
+            return -INFINITY + refs.search_state.ply as i32;
         }
 
-        let mut legal_moves = MoveGen::new_legal(&refs.board.read().unwrap());
+        // quiet checks are tried as well right at the horizon, so a mating attack or perpetual
+        // isn't missed. deeper than that only captures are, which keeps the tree small
+        if refs.search_state.ply == refs.search_state.qsearch_root_ply && !is_check {
+            legal_moves.set_iterator_mask(!EMPTY);
 
-        let board = refs.board.read().unwrap();
+            moves.extend(
+                legal_moves.filter(|&legal| *board.make_move_new(legal).checkers() != EMPTY),
+            );
+        }
 
-        let targets = board.color_combined(!board.side_to_move());
-        legal_moves.set_iterator_mask(*targets);
+        for legal in moves {
+            // captures and checks that lose material on the exchange can't raise alpha, unless
+            // they are needed to get out of check
+            if !is_check && see(&board, legal, &refs.search_params.eval_weights) < 0 {
+                continue;
+            }
 
-        drop(board);
+            let old_pos = refs.board;
+            let old_psqt = refs.psqt;
 
-        for legal in legal_moves {
-            let old_pos = *refs.board.read().unwrap();
+            refs.board = old_pos.make_move_new(legal);
 
-            let new_move = refs.board.read().unwrap().make_move_new(legal);
+            refs.psqt = material_and_psqt_after(
+                &old_pos,
+                legal,
+                old_psqt,
+                &refs.search_params.eval_weights,
+            );
 
-            *refs.board.write().unwrap() = new_move;
+            update_accumulator(refs, &old_pos, Some(legal));
 
             refs.search_state.ply += 1;
 
@@ -351,13 +1127,26 @@ impl Search {
                 refs.search_state.seldepth = refs.search_state.ply;
             }
 
+            let qdepth = refs.search_state.ply - refs.search_state.qsearch_root_ply;
+
+            if qdepth > refs.search_state.max_qdepth {
+                refs.search_state.max_qdepth = qdepth;
+            }
+
             let mut node_pv: Vec<ChessMove> = Vec::new();
 
-            let score = -Self::quiescence(-beta, -alpha, &mut node_pv, refs);
+            // captures are the only way into a dead draw, so check for it here as well
+            let score = if is_insufficient_material(refs) {
+                -draw_score(refs)
+            } else {
+                -Self::quiescence(-beta, -alpha, &mut node_pv, refs)
+            };
 
             refs.search_state.ply -= 1;
 
-            *refs.board.write().unwrap() = old_pos;
+            refs.board = old_pos;
+
+            refs.psqt = old_psqt;
 
             if score >= beta {
                 return beta;
@@ -376,159 +1165,528 @@ impl Search {
     }
 }
 
-fn is_draw(refs: &mut SearchRefs) -> bool {
-    is_insufficient_material(refs) || is_threefold_repetition(refs) || is_fifty_move_rule(refs)
-}
+// positions inside the quiescence search aren't in the history, but they're reached by captures,
+// which mostly reset the clock, so the last entry is only slightly off as a guess
+fn evaluate(refs: &mut SearchRefs) -> i32 {
+    debug_assert_eq!(
+        refs.psqt,
+        material_and_psqt(&refs.board, &refs.search_params.eval_weights)
+    );
 
-fn is_threefold_repetition(refs: &mut SearchRefs) -> bool {
-    let board = refs.board.read().unwrap();
+    if let Some(network) = &refs.search_params.network {
+        let accumulator = &refs.search_state.accumulators[refs.search_state.ply as usize];
 
-    let mut count = 0;
+        debug_assert_eq!(*accumulator, network.refresh(&refs.board));
 
-    for i in 0..refs.history.len() {
-        if refs.history[i].hash == board.get_hash() {
-            count += 1;
-        }
+        return network.evaluate(accumulator, refs.board.side_to_move());
     }
 
-    count >= 3
+    let halfmove_clock = refs.history.last().map_or(0, |entry| entry.halfmove_clock);
+
+    evaluate_position(
+        &refs.board,
+        refs.psqt,
+        halfmove_clock,
+        &mut refs.search_state.pawn_table,
+    )
 }
 
-fn is_fifty_move_rule(refs: &mut SearchRefs) -> bool {
-    let mut count = 0;
+// fills in the network accumulator for the next ply from the one for this ply, before the move
+// to it is searched. a null move changes no pieces, so the accumulator is just copied
+fn update_accumulator(refs: &mut SearchRefs, old_pos: &Board, mov: Option<ChessMove>) {
+    let search_params = refs.search_params;
 
-    for i in 0..refs.history.len() {
-        if refs.history[i].is_reversible_move {
-            count += 1;
-        } else {
-            count = 0;
-        }
+    let network = match &search_params.network {
+        Some(network) => network,
+        None => return,
+    };
 
-        if count >= 100 {
-            return true;
-        }
-    }
+    let ply = refs.search_state.ply as usize;
 
-    false
-}
+    let (parents, children) = refs.search_state.accumulators.split_at_mut(ply + 1);
 
-fn is_insufficient_material(refs: &mut SearchRefs) -> bool {
-    let board = refs.board.read().unwrap();
-
-    let white_pawn_count = (board.pieces(Piece::Pawn) & board.color_combined(Color::White))
-        .0
-        .count_ones();
-
-    let black_pawn_count = (board.pieces(Piece::Pawn) & board.color_combined(Color::Black))
-        .0
-        .count_ones();
-
-    let white_bishop_count = (board.pieces(Piece::Bishop) & board.color_combined(Color::White))
-        .0
-        .count_ones();
-    let black_bishop_count = (board.pieces(Piece::Bishop) & board.color_combined(Color::Black))
-        .0
-        .count_ones();
-
-    let white_knight_count = (board.pieces(Piece::Knight) & board.color_combined(Color::White))
-        .0
-        .count_ones();
-    let black_knight_count = (board.pieces(Piece::Knight) & board.color_combined(Color::Black))
-        .0
-        .count_ones();
-
-    let white_rook_count = (board.pieces(Piece::Rook) & board.color_combined(Color::White))
-        .0
-        .count_ones();
-    let black_rook_count = (board.pieces(Piece::Rook) & board.color_combined(Color::Black))
-        .0
-        .count_ones();
-
-    let white_queen_count = (board.pieces(Piece::Queen) & board.color_combined(Color::White))
-        .0
-        .count_ones();
-    let black_queen_count = (board.pieces(Piece::Queen) & board.color_combined(Color::Black))
-        .0
-        .count_ones();
-
-    if white_pawn_count > 0
-        || black_pawn_count > 0
-        || white_rook_count > 0
-        || black_rook_count > 0
-        || white_queen_count > 0
-        || black_queen_count > 0
-    {
-        return false;
+    match mov {
+        Some(mov) => network.update(old_pos, mov, &refs.board, &parents[ply], &mut children[0]),
+        None => children[0].clone_from(&parents[ply]),
     }
+}
 
-    if white_bishop_count <= 1 && black_bishop_count == 0 {
-        return true;
+// a draw is worth -contempt to the side the engine is playing and +contempt to its opponent, so
+// a positive contempt makes the engine avoid draws and a negative one makes it seek them out
+fn draw_score(refs: &SearchRefs) -> i32 {
+    let contempt = refs.search_params.options.contempt;
+
+    if refs.search_state.ply.is_multiple_of(2) {
+        -contempt
+    } else {
+        contempt
     }
+}
 
-    if white_bishop_count == 0 && black_bishop_count <= 1 {
-        return true;
+// the castling rights are kept outside the board in chess960, so they're mixed in here
+fn position_hash(refs: &SearchRefs) -> u64 {
+    refs.board.get_hash() ^ refs.castling_rooks.hash()
+}
+
+fn is_draw(refs: &mut SearchRefs) -> bool {
+    is_insufficient_material(refs) || is_repetition(refs) || is_fifty_move_rule(refs)
+}
+
+// a position that already occurred on the current search path is a draw straight away, since
+// whoever repeated it can just as well do so again. a repetition of the game before the root
+// still has to be a real threefold. nothing from before the last pawn move or capture can
+// recur, so the scan stops at the position that move led to
+fn is_repetition(refs: &mut SearchRefs) -> bool {
+    let hash = position_hash(refs);
+
+    let current = refs.history.len() - 1;
+
+    let mut count = 0;
+
+    for (index, entry) in refs.history.iter().enumerate().rev() {
+        if entry.hash == hash {
+            count += 1;
+
+            if index != current && index >= refs.search_state.root_history_len {
+                return true;
+            }
+        }
+
+        if entry.halfmove_clock == 0 {
+            break;
+        }
     }
 
-    if white_knight_count <= 1 && black_knight_count == 0 {
-        return true;
+    count >= 3
+}
+
+fn is_fifty_move_rule(refs: &mut SearchRefs) -> bool {
+    refs.history
+        .last()
+        .is_some_and(|entry| entry.halfmove_clock >= 100)
+}
+
+// with no pawns or major pieces and at most one minor piece left (KvK, KNvK, KBvK) the
+// position can never be won, which only takes a couple of bitboard operations to see. the
+// same goes for any number of bishops if they are all on squares of one colour, since they
+// can then never attack a king on the other colour
+fn is_insufficient_material(refs: &mut SearchRefs) -> bool {
+    let board = refs.board;
+
+    let pawns_and_majors =
+        board.pieces(Piece::Pawn) | board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+
+    if pawns_and_majors != EMPTY {
+        return false;
     }
 
-    if white_knight_count == 0 && black_knight_count <= 1 {
+    let bishops = *board.pieces(Piece::Bishop);
+    let knights = *board.pieces(Piece::Knight);
+
+    if (bishops | knights).0.count_ones() <= 1 {
         return true;
     }
 
-    false
+    knights == EMPTY && (bishops & LIGHT_SQUARES == EMPTY || bishops & !LIGHT_SQUARES == EMPTY)
 }
 
-fn move_ordering(refs: &mut SearchRefs, pv: Option<ChessMove>) -> Vec<ChessMove> {
-    let board = refs.board.read().unwrap();
+fn move_ordering(
+    refs: &mut SearchRefs,
+    pv: Option<ChessMove>,
+    tt_move: Option<ChessMove>,
+    countermove: Option<ChessMove>,
+) -> Vec<ChessMove> {
+    let board = refs.board;
+    let weights = &refs.search_params.eval_weights;
 
     let mut legal_moves = MoveGen::new_legal(&board);
 
     let mut moves = Vec::with_capacity(legal_moves.len());
 
-    let pv_square_bitboard = if let Some(pv) = pv {
-        moves.push(pv);
+    // the pv move from the previous iteration goes first, then the transposition table move.
+    // the tt move can come from a hash collision, so it has to be checked for legality
+    for hash_move in [pv, tt_move].into_iter().flatten() {
+        if chess960::is_legal(&board, &refs.castling_rooks, hash_move)
+            && !moves.contains(&hash_move)
+        {
+            moves.push(hash_move);
+        }
+    }
 
-        BitBoard::from_square(pv.get_dest())
-    } else {
-        EMPTY
-    };
+    let hash_moves = moves.clone();
 
-    let targets = *board.color_combined(!board.side_to_move()) & !pv_square_bitboard;
+    let targets = *board.color_combined(!board.side_to_move());
 
     legal_moves.set_iterator_mask(targets);
 
-    for legal in &mut legal_moves {
-        moves.push(legal);
-    }
+    let mut captures = (&mut legal_moves)
+        .filter(|legal| !hash_moves.contains(legal))
+        .collect::<Vec<_>>();
+
+    // most valuable victim first, and the least valuable attacker among equal victims
+    captures.sort_by_key(|capture| {
+        let victim = board.piece_on(capture.get_dest()).unwrap();
+        let attacker = board.piece_on(capture.get_source()).unwrap();
 
-    legal_moves.set_iterator_mask(!EMPTY & !pv_square_bitboard);
+        -(weights.piece_value(victim) * 10 - weights.piece_value(attacker))
+    });
 
-    for legal in legal_moves {
-        moves.push(legal);
+    moves.append(&mut captures);
+
+    legal_moves.set_iterator_mask(!EMPTY);
+
+    let mut quiets = legal_moves
+        .chain(castling_moves(&board, &refs.castling_rooks))
+        .filter(|legal| !hash_moves.contains(legal))
+        .collect::<Vec<_>>();
+
+    // killers are only ever quiet moves, so they go right after the captures
+    for killer in refs.search_state.killers[refs.search_state.ply as usize]
+        .into_iter()
+        .flatten()
+    {
+        if let Some(index) = quiets.iter().position(|&quiet| quiet == killer) {
+            moves.push(quiets.remove(index));
+        }
+    }
+
+    // then the reply that last refuted the opponent's previous move
+    if let Some(countermove) = countermove {
+        if let Some(index) = quiets.iter().position(|&quiet| quiet == countermove) {
+            moves.push(quiets.remove(index));
+        }
     }
 
+    let side = board.side_to_move().to_index();
+    let butterfly = &refs.search_state.butterfly[side];
+
+    // the remaining quiets by how often they caused cutoffs. the sort is stable, so moves with
+    // equal history keep their generator order and searches stay reproducible
+    quiets.sort_by_key(|quiet| {
+        -butterfly[quiet.get_source().to_index()][quiet.get_dest().to_index()]
+    });
+
+    moves.append(&mut quiets);
+
     moves
 }
 
+// moves the entry towards the bonus, scaled down the closer it already is to MAX_HISTORY, so
+// repeated cutoffs saturate instead of overflowing and old scores gradually lose their weight
+fn update_history(entry: &mut i32, bonus: i32) {
+    let bonus = bonus.clamp(-MAX_HISTORY, MAX_HISTORY);
+
+    *entry += bonus - *entry * bonus.abs() / MAX_HISTORY;
+}
+
+// countermoves are indexed by the piece the opponent moved and the square it moved to,
+// which is where that piece still stands in the current position
+fn countermove_slot<'a>(
+    refs: &'a mut SearchRefs,
+    prev_move: ChessMove,
+) -> Option<&'a mut Option<ChessMove>> {
+    let piece = refs.board.piece_on(prev_move.get_dest())?;
+
+    Some(&mut refs.search_state.countermoves[piece.to_index()][prev_move.get_dest().to_index()])
+}
+
+fn late_move_reduction(depth: u8, move_number: u16) -> u8 {
+    static LMR_TABLE: OnceLock<[[u8; 64]; 64]> = OnceLock::new();
+
+    let table = LMR_TABLE.get_or_init(|| {
+        let mut table = [[0; 64]; 64];
+
+        for (depth, row) in table.iter_mut().enumerate().skip(1) {
+            for (move_number, reduction) in row.iter_mut().enumerate().skip(1) {
+                *reduction = (0.75 + (depth as f64).ln() * (move_number as f64).ln() / 2.25) as u8;
+            }
+        }
+
+        table
+    });
+
+    table[(depth as usize).min(63)][(move_number as usize).min(63)]
+}
+
+// deeper nodes and nodes where we are far ahead can afford a bigger reduction, since the
+// null move search only has to confirm that we are still above beta
+fn null_move_reduction(depth: u8, static_eval: i32, beta: i32) -> u8 {
+    let margin_reduction = ((static_eval - beta) / NULL_MOVE_EVAL_MARGIN).clamp(0, 3) as u8;
+
+    3 + depth / 6 + margin_reduction
+}
+
+// with only king and pawns left zugzwang is common, and the null move assumption that
+// passing is never better than moving breaks down
+fn has_non_pawn_material(board: &Board) -> bool {
+    let pieces = board.pieces(Piece::Knight)
+        | board.pieces(Piece::Bishop)
+        | board.pieces(Piece::Rook)
+        | board.pieces(Piece::Queen);
+
+    pieces & board.color_combined(board.side_to_move()) != EMPTY
+}
+
+// a chess960 castle is the king taking its own rook, which isn't a capture
+fn is_quiet(board: &Board, mov: ChessMove) -> bool {
+    let is_capture = board.color_on(mov.get_dest()) == Some(!board.side_to_move())
+        || (board.piece_on(mov.get_source()) == Some(Piece::Pawn)
+            && mov.get_source().get_file() != mov.get_dest().get_file());
+
+    !is_capture && mov.get_promotion().is_none()
+}
+
+// static exchange evaluation: the material balance after both sides keep recapturing on the
+// destination square with their least valuable attacker, each side being free to stop when
+// carrying on would lose more. pins are ignored, so this is only an estimate
+fn see(board: &Board, mov: ChessMove, weights: &EvalWeights) -> i32 {
+    let from = mov.get_source();
+    let to = mov.get_dest();
+
+    let mut occupied = *board.combined() ^ BitBoard::from_square(from);
+
+    let victim = match board.piece_on(to) {
+        Some(victim) => weights.piece_value(victim),
+
+        // a pawn moving diagonally onto an empty square is an en passant capture
+        None if board.piece_on(from) == Some(Piece::Pawn) && from.get_file() != to.get_file() => {
+            occupied ^= BitBoard::from_square(board.en_passant().unwrap());
+
+            weights.piece_value(Piece::Pawn)
+        }
+
+        None => 0,
+    };
+
+    let mut attacker = mov
+        .get_promotion()
+        .unwrap_or_else(|| board.piece_on(from).unwrap());
+
+    let mut gain = [0; 32];
+    gain[0] = victim;
+
+    let mut side = !board.side_to_move();
+    let mut d = 0;
+
+    loop {
+        let attackers = attackers_to(board, to, occupied) & occupied & *board.color_combined(side);
+
+        let next = [
+            Piece::Pawn,
+            Piece::Knight,
+            Piece::Bishop,
+            Piece::Rook,
+            Piece::Queen,
+            Piece::King,
+        ]
+        .into_iter()
+        .find_map(|piece| {
+            let pieces = attackers & *board.pieces(piece);
+
+            (pieces != EMPTY).then(|| (piece, pieces.to_square()))
+        });
+
+        let Some((piece, square)) = next else {
+            break;
+        };
+
+        d += 1;
+
+        // the value of capturing the piece that just moved here, minus whatever the opponent
+        // had already won
+        gain[d] = weights.piece_value(attacker) - gain[d - 1];
+
+        if d == gain.len() - 1 {
+            break;
+        }
+
+        occupied ^= BitBoard::from_square(square);
+        attacker = piece;
+        side = !side;
+    }
+
+    while d > 0 {
+        gain[d - 1] = -(-gain[d - 1]).max(gain[d]);
+        d -= 1;
+    }
+
+    gain[0]
+}
+
+// every piece of either colour attacking the square, with sliders seeing through anything
+// that has already been removed from the occupancy
+fn attackers_to(board: &Board, square: Square, occupied: BitBoard) -> BitBoard {
+    let bishops = *board.pieces(Piece::Bishop) | *board.pieces(Piece::Queen);
+    let rooks = *board.pieces(Piece::Rook) | *board.pieces(Piece::Queen);
+
+    let pawns = *board.pieces(Piece::Pawn);
+
+    (get_pawn_attacks(
+        square,
+        Color::White,
+        pawns & *board.color_combined(Color::Black),
+    )) | (get_pawn_attacks(
+        square,
+        Color::Black,
+        pawns & *board.color_combined(Color::White),
+    )) | (get_knight_moves(square) & *board.pieces(Piece::Knight))
+        | (get_bishop_moves(square, occupied) & bishops)
+        | (get_rook_moves(square, occupied) & rooks)
+        | (get_king_moves(square) & *board.pieces(Piece::King))
+}
+
+// a search of the whole position to a fixed depth, as go depth sets up
+fn depth_params(board: &Board, depth: u8) -> SearchParams {
+    SearchParams {
+        search_mode: SearchMode::Infinite,
+        move_time: Duration::default(),
+        game_time: GameTime::default(),
+        depth: Some(depth),
+        search_moves: Vec::new(),
+        history: vec![HistoryEntry {
+            hash: board.get_hash(),
+            halfmove_clock: 0,
+        }],
+        castling_rooks: CastlingRooks::default(),
+        ponder: false,
+        options: SearchOptions::default(),
+        eval_weights: Arc::new(EvalWeights::default()),
+        network: None,
+    }
+}
+
+// pawn moves and captures can never be undone, so they reset the clock and no position from
+// before one can repeat. any other move advances the clock of the last entry in the history
+pub fn halfmove_clock_after(history: &[HistoryEntry], board: &Board, mov: ChessMove) -> u16 {
+    let is_reversible_move = !(board.piece_on(mov.get_source()) == Some(Piece::Pawn)
+        || board.color_on(mov.get_dest()) == Some(!board.side_to_move()));
+
+    if is_reversible_move {
+        history.last().map_or(0, |entry| entry.halfmove_clock) + 1
+    } else {
+        0
+    }
+}
+
+// a quiet move that caused a cutoff is likely to do the same in sibling nodes at this ply
+fn store_killer(refs: &mut SearchRefs, killer: ChessMove) {
+    let killers = &mut refs.search_state.killers[refs.search_state.ply as usize];
+
+    if killers[0] != Some(killer) {
+        killers[1] = killers[0];
+        killers[0] = Some(killer);
+    }
+}
+
+fn report_root_progress(refs: &mut SearchRefs, mov: ChessMove) {
+    let elapsed = refs.search_state.start_time.unwrap().elapsed();
+
+    if elapsed > CURRMOVE_REPORT_DELAY {
+        let info = SearchInformation::CurrMove(mov, refs.search_state.root_move_number);
+
+        refs.report_tx
+            .send(Information::SearchInformation(info))
+            .unwrap();
+    }
+}
+
+// the opponent played the move we were pondering on, so the search carries on as a normal timed
+// one. the time was allocated from the clock sent with go ponder, and everything since then
+// counts against it, since the search has been working on the position the whole time. our
+// clock only started at the ponderhit, so this can only stop sooner than the clock demands,
+// and a long ponder is turned into a move straight away. a search that had already finished,
+// say on finding a mate, is waiting in wait_for_ponder_end and returns as soon as it's told
+fn ponder_hit(refs: &mut SearchRefs) {
+    refs.search_state.pondering = false;
+}
+
+// a best move must not be sent while pondering, so a search that finished early waits for the gui
+// to either stop it or tell us the opponent played the expected move
+fn wait_for_ponder_end(refs: &mut SearchRefs) {
+    while refs.search_state.pondering && refs.search_state.terminate == SearchTerminate::Nothing {
+        match refs.control_rx.recv() {
+            Ok(SearchCommand::Stop) => refs.search_state.terminate = SearchTerminate::Stop,
+            Ok(SearchCommand::Quit) | Err(_) => refs.search_state.terminate = SearchTerminate::Quit,
+            Ok(SearchCommand::PonderHit) => ponder_hit(refs),
+            Ok(cmd @ (SearchCommand::ResizeHash(_) | SearchCommand::ClearHash)) => {
+                refs.search_state.deferred.push_back(cmd)
+            }
+            Ok(SearchCommand::Start(_) | SearchCommand::Nothing) => (),
+        }
+    }
+}
+
+// a search that finished early (forced mate, depth exhausted) still has to respect the
+// minimum thinking time, but a stop or quit from the gui cuts the wait short. any other
+// command is handled and the wait carries on
+fn wait_minimum_thinking_time(refs: &mut SearchRefs) {
+    let end =
+        refs.search_state.start_time.unwrap() + refs.search_params.options.minimum_thinking_time;
+
+    loop {
+        let remaining = end.saturating_duration_since(Instant::now());
+
+        if remaining.is_zero() {
+            return;
+        }
+
+        match refs.control_rx.recv_timeout(remaining) {
+            Ok(SearchCommand::Stop) => {
+                refs.search_state.terminate = SearchTerminate::Stop;
+
+                return;
+            }
+            Ok(SearchCommand::Quit) => {
+                refs.search_state.terminate = SearchTerminate::Quit;
+
+                return;
+            }
+            Ok(SearchCommand::PonderHit) => ponder_hit(refs),
+            Ok(cmd @ (SearchCommand::ResizeHash(_) | SearchCommand::ClearHash)) => {
+                refs.search_state.deferred.push_back(cmd)
+            }
+            Ok(SearchCommand::Start(_) | SearchCommand::Nothing) => (),
+            Err(_) => return,
+        }
+    }
+}
+
 fn check_terminate(refs: &mut SearchRefs) {
     match refs.control_rx.try_recv().unwrap_or(SearchCommand::Nothing) {
         SearchCommand::Stop => refs.search_state.terminate = SearchTerminate::Stop,
         SearchCommand::Quit => refs.search_state.terminate = SearchTerminate::Quit,
+        SearchCommand::PonderHit => ponder_hit(refs),
+
+        // the table is in use, so it's only changed once the search is over
+        cmd @ (SearchCommand::ResizeHash(_) | SearchCommand::ClearHash) => {
+            refs.search_state.deferred.push_back(cmd)
+        }
 
         SearchCommand::Start(_) | SearchCommand::Nothing => (),
     };
 
+    // the first iteration is always finished, even with no time left after the move overhead,
+    // so there's a searched move to play rather than whichever one was generated first
+    if refs.search_state.depth <= 1 {
+        return;
+    }
+
     match refs.search_params.search_mode {
         SearchMode::Infinite => (),
         SearchMode::MoveTime => {
             if let Some(start_time) = refs.search_state.start_time {
-                if start_time.elapsed() > refs.search_params.move_time {
+                let move_time = refs
+                    .search_params
+                    .move_time
+                    .max(refs.search_params.options.minimum_thinking_time);
+
+                if start_time.elapsed() > move_time {
                     refs.search_state.terminate = SearchTerminate::Stop;
                 }
             }
         }
+        // the clock only starts once the opponent plays the move we're pondering on
+        SearchMode::GameTime if refs.search_state.pondering => (),
         SearchMode::GameTime => {
             let elapsed = refs.search_state.start_time.unwrap().elapsed();
             let allocated = refs.search_state.allocated_time;
@@ -551,9 +1709,12 @@ fn check_terminate(refs: &mut SearchRefs) {
 }
 
 pub enum SearchCommand {
-    Start(SearchParams),
+    Start(Box<SearchParams>), // boxed, it's far bigger than the other commands
     Stop,
     Quit,
+    PonderHit,
+    ResizeHash(usize),
+    ClearHash,
     Nothing,
 }
 
@@ -565,9 +1726,42 @@ enum SearchTerminate {
 }
 
 pub struct SearchParams {
-    pub search_mode: SearchMode, // search mode
-    pub move_time: Duration,     // maximum time to search per move
-    pub game_time: GameTime,     // time left in the game
+    pub search_mode: SearchMode,        // search mode
+    pub move_time: Duration,            // maximum time to search per move
+    pub game_time: GameTime,            // time left in the game
+    pub depth: Option<u8>,              // depth to stop at, whichever the mode
+    pub search_moves: Vec<ChessMove>,   // root moves to search, or all of them if empty
+    pub history: Vec<HistoryEntry>,     // the game so far, ending with the root position
+    pub castling_rooks: CastlingRooks,  // rooks that can still castle at the root in chess960
+    pub ponder: bool,                   // started by go ponder, untimed until ponderhit
+    pub options: SearchOptions,         // options set through setoption
+    pub eval_weights: Arc<EvalWeights>, // piece values and tables to evaluate with
+    pub network: Option<Arc<Network>>,  // replaces the evaluation when loaded
+}
+
+#[derive(Clone, Copy)]
+pub struct SearchOptions {
+    pub quiescence: bool, // resolve captures at the horizon instead of evaluating directly
+    pub minimum_thinking_time: Duration, // never return a move sooner than this
+    pub move_overhead: Duration, // time lost to the gui and the connection on every move
+    pub contempt: i32,    // centipawns a draw is worth less than zero to us
+    pub ponder: bool,     // the gui may let us think on the opponent's time
+    pub aspiration_window: i32, // initial distance of each bound from the last score
+    pub aspiration_growth: i32, // factor a bound's distance grows by when it fails
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            quiescence: true,
+            minimum_thinking_time: Duration::from_millis(0),
+            move_overhead: Duration::from_millis(DEFAULT_MOVE_OVERHEAD),
+            contempt: 0,
+            ponder: false,
+            aspiration_window: DEFAULT_ASPIRATION_WINDOW,
+            aspiration_growth: DEFAULT_ASPIRATION_GROWTH,
+        }
+    }
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -578,28 +1772,49 @@ pub enum SearchMode {
 }
 
 pub struct SearchRefs<'a> {
-    board: Arc<RwLock<Board>>,
+    board: Board,
+    castling_rooks: CastlingRooks, // rooks that can still castle in chess960
+    psqt: Score,                   // material and piece-square score of the board
     search_params: &'a SearchParams,
     search_state: &'a mut SearchState,
     control_rx: &'a Receiver<SearchCommand>,
     report_tx: &'a Sender<Information>,
     history: &'a mut Vec<HistoryEntry>,
+    tt: &'a mut TranspositionTable,
 }
 
 #[derive(Clone, Copy)]
 pub struct HistoryEntry {
-    hash: u64,
-    is_reversible_move: bool,
+    pub hash: u64,           // hash of the position after the move
+    pub halfmove_clock: u16, // plies since the last pawn move or capture
 }
 
+type KillerTable = [[Option<ChessMove>; 2]; MAX_PLY as usize];
+type ButterflyTable = [[[i32; 64]; 64]; 2]; // [side to move][from][to]
+type CountermoveTable = [[Option<ChessMove>; 64]; 6]; // [piece][to] of the previous move
+type ExcludedTable = [Option<ChessMove>; MAX_PLY as usize];
+
 struct SearchState {
-    seldepth: u8,                // max depth searched
-    start_time: Option<Instant>, // time search started
-    nodes: u64,                  // number of nodes searched
-    depth: u8,                   // current depth
-    ply: u8,                     // current number of plies from root
-    terminate: SearchTerminate,  // terminate flag
-    allocated_time: Duration,    // time allocated to search
+    seldepth: u8,                      // max depth searched
+    start_time: Option<Instant>,       // time search started
+    nodes: u64,                        // number of nodes searched
+    depth: u8,                         // current depth
+    ply: u8,                           // current number of plies from root
+    terminate: SearchTerminate,        // terminate flag
+    allocated_time: Duration,          // time allocated to search
+    root_move_number: u16,             // root move being searched
+    qsearch_root_ply: u8,              // ply quiescence started at
+    max_qdepth: u8,                    // max quiescence depth reached
+    killers: KillerTable,              // quiet moves that caused cutoffs
+    butterfly: ButterflyTable,         // quiet move cutoff history
+    countermoves: CountermoveTable,    // quiet replies that refuted a move
+    excluded: ExcludedTable,           // move skipped at each ply
+    extensions: u8,                    // singular extensions on this line
+    root_history_len: usize,           // history entries from before the search
+    pawn_table: PawnHashTable,         // cached pawn-structure scores
+    accumulators: Vec<Accumulator>,    // network accumulators by ply
+    pondering: bool,                   // searching on the opponent's time
+    deferred: VecDeque<SearchCommand>, // hash commands to handle after the search
 }
 
 impl SearchState {
@@ -612,13 +1827,31 @@ impl SearchState {
             ply: 0,
             terminate: SearchTerminate::Nothing,
             allocated_time: Duration::from_secs(0),
+            root_move_number: 0,
+            qsearch_root_ply: 0,
+            max_qdepth: 0,
+            killers: [[None; 2]; MAX_PLY as usize],
+            butterfly: [[[0; 64]; 64]; 2],
+            countermoves: [[None; 64]; 6],
+            excluded: [None; MAX_PLY as usize],
+            extensions: 0,
+            root_history_len: 0,
+            pawn_table: PawnHashTable::new(),
+            accumulators: Vec::new(),
+            pondering: false,
+            deferred: VecDeque::new(),
         }
     }
 }
 
 #[derive(Debug)]
 pub enum SearchInformation {
-    BestMove(ChessMove),
+    BestMove(Option<ChessMove>, Option<ChessMove>), // best move if any, and the reply to ponder on
+    SearchTime(Duration),
+    CurrMove(ChessMove, u16), // root move being searched, and its number in the move order
+    QuiescenceDepth(u8),
+    AspirationResearches(u32), // searches repeated because the score fell outside the window
+    PawnHashHitRate(f64),
     Summary(SearchSummary),
     ExtraInfo(String),
 }
@@ -633,3 +1866,859 @@ pub struct SearchSummary {
     pub nps: u64,           // nodes per second
     pub pv: Vec<ChessMove>, // Principal Variation
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uci::mate_in_moves;
+    use std::str::FromStr;
+
+    // runs searches on the test's own thread the way the search thread does. the other ends of
+    // the channels are kept open, since the search treats them closing as a quit
+    struct TestSearch {
+        tt: TranspositionTable,
+        control_tx: Sender<SearchCommand>,
+        control_rx: Receiver<SearchCommand>,
+        report_tx: Sender<Information>,
+        report_rx: Receiver<Information>,
+    }
+
+    impl TestSearch {
+        fn new() -> Self {
+            Self::with_hash_size(DEFAULT_HASH_SIZE_MB)
+        }
+
+        fn with_hash_size(size_mb: usize) -> Self {
+            let (control_tx, control_rx) = crossbeam_channel::unbounded();
+            let (report_tx, report_rx) = crossbeam_channel::unbounded();
+
+            Self {
+                tt: TranspositionTable::new(size_mb),
+                control_tx,
+                control_rx,
+                report_tx,
+                report_rx,
+            }
+        }
+
+        fn run(&mut self, board: Board, params: &SearchParams) -> (Option<ChessMove>, SearchState) {
+            self.with_refs(board, params, |refs| Search::iterative_deepening(refs).0)
+        }
+
+        // hands the position to f set up as the root of a search, for testing parts of one
+        fn with_refs<T>(
+            &mut self,
+            board: Board,
+            params: &SearchParams,
+            f: impl FnOnce(&mut SearchRefs) -> T,
+        ) -> (T, SearchState) {
+            let mut search_state = SearchState::new();
+            let mut history = params.history.clone();
+
+            self.tt.new_search();
+
+            let mut refs = SearchRefs {
+                board,
+                castling_rooks: params.castling_rooks,
+                psqt: material_and_psqt(&board, &params.eval_weights),
+                search_params: params,
+                search_state: &mut search_state,
+                control_rx: &self.control_rx,
+                report_tx: &self.report_tx,
+                history: &mut history,
+                tt: &mut self.tt,
+            };
+
+            let result = f(&mut refs);
+
+            (result, search_state)
+        }
+
+        fn reports(&self) -> Vec<SearchInformation> {
+            self.report_rx
+                .try_iter()
+                .map(|info| match info {
+                    Information::SearchInformation(info) => info,
+                    Information::UciInformation(_) => unreachable!(),
+                })
+                .collect()
+        }
+    }
+
+    fn board(fen: &str) -> Board {
+        Board::from_str(fen).unwrap()
+    }
+
+    // the score of the deepest iteration the search finished
+    fn final_score(reports: &[SearchInformation]) -> i32 {
+        reports
+            .iter()
+            .rev()
+            .find_map(|info| match info {
+                SearchInformation::Summary(summary) => Some(summary.cp),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    fn mov(uci: &str) -> ChessMove {
+        ChessMove::from_str(uci).unwrap()
+    }
+
+    #[test]
+    fn same_best_move_without_the_tt() {
+        for fen in [
+            "r1b1kb1r/pppp1ppp/5q2/4n3/3KP3/2N3PN/PPP4P/R1BQ1B1R b kq - 0 1",
+            "q3k3/pp6/8/1N6/8/8/5PPP/6K1 w - - 0 1",
+            "r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+        ] {
+            let board = board(fen);
+
+            let params = depth_params(&board, 5);
+
+            let (with_tt, _) = TestSearch::new().run(board, &params);
+
+            // a table of a single bucket, which keeps next to nothing between nodes
+            let (without_tt, _) = TestSearch::with_hash_size(0).run(board, &params);
+
+            assert_eq!(with_tt, without_tt, "{fen}");
+        }
+    }
+
+    #[test]
+    fn null_move_reduction_grows_with_depth_and_margin() {
+        // three plies, and one more every six plies of depth
+        assert_eq!(null_move_reduction(NULL_MOVE_MIN_DEPTH, 0, 0), 3);
+        assert_eq!(null_move_reduction(5, 0, 0), 3);
+        assert_eq!(null_move_reduction(6, 0, 0), 4);
+        assert_eq!(null_move_reduction(12, 0, 0), 5);
+
+        // one more for each full margin the static eval is above beta, at most three
+        assert_eq!(null_move_reduction(5, NULL_MOVE_EVAL_MARGIN - 1, 0), 3);
+        assert_eq!(null_move_reduction(5, NULL_MOVE_EVAL_MARGIN, 0), 4);
+        assert_eq!(null_move_reduction(5, 3 * NULL_MOVE_EVAL_MARGIN, 0), 6);
+        assert_eq!(null_move_reduction(5, 100 * NULL_MOVE_EVAL_MARGIN, 0), 6);
+        assert_eq!(null_move_reduction(5, 400 + NULL_MOVE_EVAL_MARGIN, 400), 4);
+    }
+
+    #[test]
+    fn history_bonus_saturates_instead_of_overflowing() {
+        let mut entry = 0;
+
+        update_history(&mut entry, 100);
+
+        assert_eq!(entry, 100);
+
+        // halfway to the maximum, only half of the bonus is kept
+        let mut entry = MAX_HISTORY / 2;
+
+        update_history(&mut entry, 100);
+
+        assert_eq!(entry, MAX_HISTORY / 2 + 50);
+
+        // however many cutoffs there are, the entry never passes the maximum either way
+        let mut entry = 0;
+
+        for depth in 1..1000 {
+            update_history(&mut entry, depth * depth);
+
+            assert!((0..=MAX_HISTORY).contains(&entry));
+        }
+
+        assert_eq!(entry, MAX_HISTORY);
+
+        for _ in 0..1000 {
+            update_history(&mut entry, -i32::MAX);
+
+            assert!((-MAX_HISTORY..=MAX_HISTORY).contains(&entry));
+        }
+
+        assert_eq!(entry, -MAX_HISTORY);
+    }
+
+    #[test]
+    fn quiets_are_ordered_by_history() {
+        let board = board("4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+
+        let params = depth_params(&board, 1);
+
+        let (moves, _) = TestSearch::new().with_refs(board, &params, |refs| {
+            let white = Color::White.to_index();
+
+            refs.search_state.butterfly[white][Square::A1.to_index()][Square::A7.to_index()] = 50;
+            refs.search_state.butterfly[white][Square::E1.to_index()][Square::D2.to_index()] = 500;
+            refs.search_state.butterfly[white][Square::A1.to_index()][Square::A8.to_index()] = -50;
+
+            move_ordering(refs, None, None, None)
+        });
+
+        assert_eq!(moves[0], mov("e1d2"));
+        assert_eq!(moves[1], mov("a1a7"));
+        assert_eq!(moves.last(), Some(&mov("a1a8")));
+    }
+
+    #[test]
+    fn countermove_is_recorded_after_a_quiet_cutoff() {
+        // black's king has just come back to e8, and white only has quiet moves. beta is so low
+        // that the first move searched cuts off
+        let board = board("4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+
+        let params = depth_params(&board, 1);
+
+        let prev_move = mov("e7e8");
+
+        let (score, search_state) = TestSearch::new().with_refs(board, &params, |refs| {
+            refs.search_state.start_time = Some(Instant::now());
+
+            Search::negamax(
+                refs,
+                &mut Vec::new(),
+                1,
+                -INFINITY,
+                -INFINITY + 1,
+                Some(prev_move),
+            )
+        });
+
+        assert_eq!(score, -INFINITY + 1);
+
+        let refutation = search_state.killers[0][0].unwrap();
+
+        assert!(is_quiet(&board, refutation));
+        assert_eq!(
+            search_state.countermoves[Piece::King.to_index()][Square::E8.to_index()],
+            Some(refutation)
+        );
+
+        // a countermove is tried before the other quiets, whatever order they're generated in
+        let (moves, _) = TestSearch::new().with_refs(board, &params, |refs| {
+            move_ordering(refs, None, None, Some(mov("e1f2")))
+        });
+
+        assert_ne!(refutation, mov("e1f2"));
+        assert_eq!(moves[0], mov("e1f2"));
+    }
+
+    #[test]
+    fn minimum_thinking_time_holds_back_a_quick_move() {
+        // a single legal move, found at once
+        let board = board("7k/8/8/8/8/8/1r6/K7 w - - 0 1");
+
+        let params = SearchParams {
+            options: SearchOptions {
+                minimum_thinking_time: Duration::from_millis(200),
+                ..SearchOptions::default()
+            },
+            ..depth_params(&board, 1)
+        };
+
+        let mut search = TestSearch::new();
+
+        // commands that don't end the search don't end the wait either
+        search.control_tx.send(SearchCommand::ClearHash).unwrap();
+
+        let start = Instant::now();
+
+        let (best_move, search_state) = search.run(board, &params);
+
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert_eq!(best_move, Some(mov("a1b2")));
+        assert!(matches!(
+            search_state.deferred.front(),
+            Some(SearchCommand::ClearHash)
+        ));
+
+        // a stop does
+        search.control_tx.send(SearchCommand::Stop).unwrap();
+
+        let start = Instant::now();
+
+        search.run(board, &params);
+
+        assert!(start.elapsed() < Duration::from_millis(200));
+    }
+
+    #[test]
+    fn reports_how_deep_quiescence_went() {
+        // pieces hanging on both sides, so captures run on past the horizon
+        let captures =
+            board("r1bqk2r/ppp2ppp/2n2n2/2bpp3/2BPP3/2N2N2/PPP2PPP/R1BQK2R w KQkq - 0 6");
+
+        let mut search = TestSearch::new();
+
+        let (_, search_state) = search.run(captures, &depth_params(&captures, 3));
+
+        assert!(search_state.max_qdepth >= 2);
+
+        // reported after each iteration, so the last report has the whole search
+        let reported = search
+            .reports()
+            .into_iter()
+            .rev()
+            .find_map(|info| match info {
+                SearchInformation::QuiescenceDepth(qdepth) => Some(qdepth),
+                _ => None,
+            });
+
+        assert_eq!(reported, Some(search_state.max_qdepth));
+
+        // a position without captures never gets past the quiet checks at the horizon
+        let quiet = board("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+
+        let (_, search_state) = TestSearch::new().run(quiet, &depth_params(&quiet, 3));
+
+        assert_eq!(search_state.max_qdepth, 0);
+    }
+
+    #[test]
+    fn go_ponder_with_the_ponder_option_off_searches_until_stopped() {
+        let board = board("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+
+        let params = SearchParams {
+            search_mode: SearchMode::GameTime,
+            game_time: GameTime::default(),
+            depth: None,
+            ponder: true,
+            options: SearchOptions {
+                ponder: false,
+                ..SearchOptions::default()
+            },
+            ..depth_params(&board, 1)
+        };
+
+        let mut search = TestSearch::new();
+
+        let control_tx = search.control_tx.clone();
+
+        let stopper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+
+            control_tx.send(SearchCommand::Stop).unwrap();
+        });
+
+        let (best_move, search_state) = search.run(board, &params);
+
+        stopper.join().unwrap();
+
+        assert!(best_move.is_some());
+        assert!(search_state.terminate == SearchTerminate::Stop);
+    }
+
+    #[test]
+    fn lone_minor_piece_is_a_draw_at_every_node() {
+        let board = board("8/8/8/4k3/8/8/3B4/4K3 w - - 0 1");
+
+        let mut search = TestSearch::new();
+
+        let (best_move, search_state) = search.run(board, &depth_params(&board, 12));
+
+        // every move leads straight to a draw, so nothing below the root is searched
+        assert!(best_move.is_some());
+        assert_eq!(final_score(&search.reports()), 0);
+        assert!(search_state.nodes <= 12);
+    }
+
+    #[test]
+    fn mates_are_reported_in_moves_for_the_side_to_move() {
+        for (fen, mate) in [
+            // Ra8#, one ply
+            ("6k1/5ppp/8/8/8/8/8/R6K w - - 0 1", 1),
+            // Nf6+ gxf6 Bxf7#, three plies
+            (
+                "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 10",
+                2,
+            ),
+            // Kb8 is forced and Rh8# follows, so black is the one mated, on white's first move
+            ("k7/8/1K6/8/8/8/8/7R b - - 0 1", -1),
+        ] {
+            let board = board(fen);
+
+            let mut search = TestSearch::new();
+
+            search.run(board, &depth_params(&board, 5));
+
+            let score = final_score(&search.reports());
+
+            assert_eq!(mate_in_moves(score), Some(mate), "{fen}");
+        }
+    }
+
+    #[test]
+    fn no_best_move_without_legal_moves() {
+        for fen in [
+            // checkmated
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+            // stalemated
+            "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1",
+        ] {
+            let board = board(fen);
+
+            let (best_move, search_state) = TestSearch::new().run(board, &depth_params(&board, 5));
+
+            assert_eq!(best_move, None);
+            assert_eq!(search_state.depth, 1);
+        }
+    }
+
+    #[test]
+    fn reported_search_time_matches_elapsed_time() {
+        let board = board("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+
+        let params = SearchParams {
+            search_mode: SearchMode::MoveTime,
+            move_time: Duration::from_millis(200),
+            depth: None,
+            ..depth_params(&board, 1)
+        };
+
+        let mut search = TestSearch::new();
+
+        let start = Instant::now();
+
+        search.run(board, &params);
+
+        let elapsed = start.elapsed();
+
+        let reported = search
+            .reports()
+            .into_iter()
+            .find_map(|info| match info {
+                SearchInformation::SearchTime(time) => Some(time),
+                _ => None,
+            })
+            .unwrap();
+
+        // the whole move time is inside the reported time, which is inside the time the caller
+        // waited. how close the two are depends on the machine, so that isn't checked
+        assert!(params.move_time <= reported);
+        assert!(reported <= elapsed);
+    }
+
+    #[test]
+    fn ponderhit_turns_pondering_into_a_timed_search() {
+        let board = board("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+
+        // a twenty second clock gets about 800 ms, less than the time spent pondering
+        let params = SearchParams {
+            search_mode: SearchMode::GameTime,
+            game_time: GameTime {
+                wtime: Some(Duration::from_secs(20)),
+                btime: Some(Duration::from_secs(20)),
+                ..GameTime::default()
+            },
+            depth: None,
+            ponder: true,
+            ..depth_params(&board, 1)
+        };
+
+        let mut search = TestSearch::new();
+
+        let control_tx = search.control_tx.clone();
+
+        let ponder_time = Duration::from_millis(1200);
+
+        thread::spawn(move || {
+            thread::sleep(ponder_time);
+
+            control_tx.send(SearchCommand::PonderHit).unwrap();
+        });
+
+        let start = Instant::now();
+
+        let (best_move, search_state) = search.run(board, &params);
+
+        let elapsed = start.elapsed();
+
+        assert!(best_move.is_some());
+        assert!(!search_state.pondering);
+        assert!(search_state.terminate == SearchTerminate::Stop);
+
+        // nothing stops a search while it ponders, and the pondering used up the allocated
+        // time, so the move comes after the ponderhit but without searching for the allocated
+        // time all over again
+        assert!(search_state.allocated_time < ponder_time);
+        assert!(elapsed >= ponder_time);
+        assert!(elapsed - ponder_time < search_state.allocated_time / 2);
+    }
+
+    #[test]
+    fn ponderhit_after_a_mate_is_found_returns_at_once() {
+        // back rank mate in one, found long before the ponderhit with plenty of time left
+        let board = board("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+
+        let params = SearchParams {
+            search_mode: SearchMode::GameTime,
+            game_time: GameTime {
+                wtime: Some(Duration::from_secs(60)),
+                btime: Some(Duration::from_secs(60)),
+                ..GameTime::default()
+            },
+            depth: None,
+            ponder: true,
+            ..depth_params(&board, 1)
+        };
+
+        let mut search = TestSearch::new();
+
+        let control_tx = search.control_tx.clone();
+
+        let ponder_time = Duration::from_millis(300);
+
+        thread::spawn(move || {
+            thread::sleep(ponder_time);
+
+            control_tx.send(SearchCommand::PonderHit).unwrap();
+        });
+
+        let start = Instant::now();
+
+        let (best_move, search_state) = search.run(board, &params);
+
+        let elapsed = start.elapsed();
+
+        assert_eq!(
+            best_move,
+            Some(ChessMove::new(Square::A1, Square::A8, None))
+        );
+
+        // well before the seconds the clock allows
+        assert!(elapsed >= ponder_time);
+        assert!(elapsed - ponder_time < search_state.allocated_time / 2);
+    }
+
+    #[test]
+    fn missing_clock_for_the_side_to_move_falls_back_to_a_fixed_time() {
+        // black to move, but only white's clock is given
+        let board = board("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+
+        let params = SearchParams {
+            search_mode: SearchMode::GameTime,
+            game_time: GameTime {
+                wtime: Some(Duration::from_secs(60)),
+                btime: None,
+                ..GameTime::default()
+            },
+            depth: None,
+            ..depth_params(&board, 1)
+        };
+
+        let mut search = TestSearch::new();
+
+        let start = Instant::now();
+
+        let (best_move, search_state) = search.run(board, &params);
+
+        let elapsed = start.elapsed();
+
+        assert!(best_move.is_some());
+        assert_eq!(search_state.allocated_time, MISSING_CLOCK_TIME);
+        assert!(elapsed < 2 * MISSING_CLOCK_TIME);
+
+        assert!(search.reports().iter().any(|info| matches!(
+            info,
+            SearchInformation::ExtraInfo(message) if message.starts_with("no clock given")
+        )));
+    }
+
+    #[test]
+    fn kbnk_drives_the_king_into_a_corner_of_the_bishops_colour() {
+        // a light-squared bishop, so the mate can only be given on a8 or h1
+        let mut position = board("8/8/8/4k3/8/8/8/4KBN1 w - - 0 1");
+
+        let corner_distance = |board: &Board| {
+            let king = board.king_square(Color::Black);
+
+            [Square::A8, Square::H1]
+                .iter()
+                .map(|corner| {
+                    let files = king
+                        .get_file()
+                        .to_index()
+                        .abs_diff(corner.get_file().to_index());
+                    let ranks = king
+                        .get_rank()
+                        .to_index()
+                        .abs_diff(corner.get_rank().to_index());
+
+                    files + ranks
+                })
+                .min()
+                .unwrap()
+        };
+
+        let mut closest = corner_distance(&position);
+
+        let mut search = TestSearch::new();
+
+        let mut history = vec![HistoryEntry {
+            hash: position.get_hash(),
+            halfmove_clock: 0,
+        }];
+
+        // both sides are played by the search, so the king is defending as well as it can
+        for ply in 1..=60 {
+            let params = SearchParams {
+                history: history.clone(),
+                ..depth_params(&position, 8)
+            };
+
+            let (best_move, _) = search.run(position, &params);
+
+            position = position.make_move_new(best_move.unwrap());
+
+            closest = closest.min(corner_distance(&position));
+
+            history.push(HistoryEntry {
+                hash: position.get_hash(),
+                halfmove_clock: ply,
+            });
+        }
+
+        // from the middle of the board the king is pushed all the way into the right corner
+        assert_eq!(position.combined().popcnt(), 4);
+        assert_eq!(closest, 0);
+    }
+
+    #[test]
+    fn disabling_quiescence_misses_a_recapture_at_the_horizon() {
+        // the pawn on d5 is defended, so taking it loses the queen one ply past depth 1
+        let board = board("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1");
+
+        let mut scores = Vec::new();
+
+        for (quiescence, takes) in [(true, false), (false, true)] {
+            let params = SearchParams {
+                options: SearchOptions {
+                    quiescence,
+                    ..SearchOptions::default()
+                },
+                ..depth_params(&board, 1)
+            };
+
+            let mut search = TestSearch::new();
+
+            let (best_move, _) = search.run(board, &params);
+
+            assert_eq!(best_move == Some(mov("d1d5")), takes, "{quiescence}");
+
+            scores.push(final_score(&search.reports()));
+        }
+
+        // without the recapture the pawn looks won
+        assert!(scores[1] > scores[0] + 50, "{scores:?}");
+    }
+
+    #[test]
+    fn root_move_number_advances_with_each_root_move() {
+        let board = board("4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+
+        let params = depth_params(&board, 2);
+
+        let mut search = TestSearch::new();
+
+        let (_, search_state) = search.with_refs(board, &params, |refs| {
+            // long enough ago that every root move is reported
+            refs.search_state.start_time = Some(Instant::now() - 2 * CURRMOVE_REPORT_DELAY);
+
+            Search::negamax(refs, &mut Vec::new(), 2, -INFINITY, INFINITY, None)
+        });
+
+        let numbers = search
+            .reports()
+            .into_iter()
+            .filter_map(|info| match info {
+                SearchInformation::CurrMove(_, number) => Some(number),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let legal_moves = MoveGen::new_legal(&board).len() as u16;
+
+        // only the root moves are counted, once each, in the order they're searched
+        assert_eq!(numbers, (1..=legal_moves).collect::<Vec<_>>());
+        assert_eq!(search_state.root_move_number, legal_moves);
+    }
+
+    #[test]
+    fn transposition_table_is_kept_between_searches() {
+        let (info_tx, info_rx) = crossbeam_channel::unbounded();
+
+        let shared_board = Arc::new(RwLock::new(Board::default()));
+
+        // runs a search on the search thread and returns the last summary
+        let search = |engine: &Search, position: Board| {
+            *shared_board.write().unwrap() = position;
+
+            engine.send(SearchCommand::Start(Box::new(depth_params(&position, 7))));
+
+            let mut summary = None;
+
+            for info in info_rx.iter() {
+                match info {
+                    Information::SearchInformation(SearchInformation::Summary(s)) => {
+                        summary = Some(s)
+                    }
+                    Information::SearchInformation(SearchInformation::BestMove(..)) => break,
+                    _ => (),
+                }
+            }
+
+            summary.unwrap()
+        };
+
+        let start = board("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+
+        let mut engine = Search::new();
+        engine.init(info_tx.clone(), Arc::clone(&shared_board));
+
+        let first = search(&engine, start);
+
+        // the position two plies down the principal variation was searched by the first search
+        let next = first.pv[..2]
+            .iter()
+            .fold(start, |position, &mov| position.make_move_new(mov));
+
+        let kept = search(&engine, next);
+
+        let mut fresh_engine = Search::new();
+        fresh_engine.init(info_tx, Arc::clone(&shared_board));
+
+        let fresh = search(&fresh_engine, next);
+
+        assert!(kept.nodes < fresh.nodes, "{} {}", kept.nodes, fresh.nodes);
+
+        // clearing the hash leaves nothing to reuse
+        engine.send(SearchCommand::ClearHash);
+
+        assert_eq!(search(&engine, next).nodes, fresh.nodes);
+
+        for engine in [&mut engine, &mut fresh_engine] {
+            engine.send(SearchCommand::Quit);
+            engine.wait();
+        }
+    }
+
+    #[test]
+    fn see_plays_out_known_exchanges() {
+        let weights = EvalWeights::default();
+
+        let [pawn, knight, rook, queen] =
+            [Piece::Pawn, Piece::Knight, Piece::Rook, Piece::Queen].map(|p| weights.piece_value(p));
+
+        for (fen, capture, expected) in [
+            // pawn takes a knight defended by a pawn, and is taken back
+            ("4k3/8/4p3/3n4/4P3/8/8/4K3 w - - 0 1", "e4d5", knight - pawn),
+            // knight takes a pawn defended by a pawn
+            ("4k3/8/4p3/3p4/8/4N3/8/4K3 w - - 0 1", "e3d5", pawn - knight),
+            // nothing defends the pawn
+            ("4k3/8/8/3p4/8/8/8/3RK3 w - - 0 1", "d1d5", pawn),
+            // the rook behind the first wins the exchange of rooks on d5
+            ("3r2k1/8/8/3p4/8/8/3R4/3R2K1 w - - 0 1", "d2d5", pawn),
+            // without it the rook is lost for the pawn
+            ("3r2k1/8/8/3p4/8/8/3R4/6K1 w - - 0 1", "d2d5", pawn - rook),
+            // taking a defended queen with a pawn is still worth it
+            ("4k3/2p5/3q4/4P3/8/8/8/4K3 w - - 0 1", "e5d6", queen - pawn),
+            // the king can't take back on a square the rook behind the queen sees
+            ("8/8/4k3/3p4/8/3Q4/8/3R2K1 w - - 0 1", "d3d5", pawn),
+            // en passant, with nothing to take back
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", pawn),
+        ] {
+            assert_eq!(see(&board(fen), mov(capture), &weights), expected, "{fen}");
+        }
+    }
+
+    #[test]
+    fn quiescence_doesnt_stand_pat_in_check() {
+        // black is three knights for a rook up, above beta, but mated on the back rank
+        let board = board("R5k1/5ppp/8/8/8/n7/nn6/6K1 b - - 0 1");
+
+        let params = depth_params(&board, 1);
+
+        let (score, _) = TestSearch::new().with_refs(board, &params, |refs| {
+            refs.search_state.start_time = Some(Instant::now());
+
+            assert!(evaluate(refs) >= 100);
+
+            Search::quiescence(-100, 100, &mut Vec::new(), refs)
+        });
+
+        assert_eq!(score, -INFINITY);
+    }
+
+    #[test]
+    fn insufficient_material_for_each_combination() {
+        for (fen, insufficient) in [
+            ("4k3/8/8/8/8/8/8/4K3 w - - 0 1", true),     // kvk
+            ("4k3/8/8/8/8/8/8/4KN2 w - - 0 1", true),    // knvk
+            ("4k3/8/8/8/8/8/8/4KB2 w - - 0 1", true),    // kbvk
+            ("4kb2/8/8/8/8/8/8/4K3 w - - 0 1", true),    // kvkb
+            ("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1", true),  // kbvkb, both on dark squares
+            ("2b1k3/8/8/8/8/8/8/4KB2 w - - 0 1", true),  // kbvkb, both on light squares
+            ("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1", false), // kbbvk, one of each colour
+            ("4k3/8/8/8/8/8/8/4KBB1 w - - 0 1", false),  // kbbvk, one of each colour
+            ("4k3/8/8/8/8/8/B7/4KB2 w - - 0 1", true),   // kbbvk, both on light squares
+            ("4kb2/8/8/8/8/8/8/4KB2 w - - 0 1", false),  // kbvkb, opposite colours
+            ("4kn2/8/8/8/8/8/8/4KB2 w - - 0 1", false),  // kbvkn
+            ("4kn2/8/8/8/8/8/8/4KN2 w - - 0 1", false),  // knvkn
+            ("4k3/8/8/8/8/8/8/4KNN1 w - - 0 1", false),  // knnvk
+            ("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", false),  // kpvk
+            ("4k3/8/8/8/8/8/8/4KR2 w - - 0 1", false),   // krvk
+            ("4k3/8/8/8/8/8/8/4KQ2 w - - 0 1", false),   // kqvk
+        ] {
+            let board = board(fen);
+
+            let (result, _) =
+                TestSearch::new().with_refs(board, &depth_params(&board, 1), |refs| {
+                    is_insufficient_material(refs)
+                });
+
+            assert_eq!(result, insufficient, "{fen}");
+        }
+    }
+
+    #[test]
+    fn narrow_aspiration_window_is_widened_until_fully_open() {
+        // the mate with the rooks only turns up at depth 4, the first depth searched with a
+        // window, so the bound above the depth 3 score fails every time until it's opened up
+        let board = board("6k1/pp4p1/2p5/2bp4/8/P5Pb/1P3rrP/2BRRN1K b - - 0 1");
+
+        // the bound is widened to 2, 4, ..., 512 and then opened, to 100 and 400 and then
+        // opened, to 100 and 1000 and then opened, and to 1000 and then opened
+        for (window, growth, expected) in [(1, 2, 10), (25, 4, 3), (10, 10, 3), (500, 2, 2)] {
+            let params = SearchParams {
+                options: SearchOptions {
+                    aspiration_window: window,
+                    aspiration_growth: growth,
+                    ..SearchOptions::default()
+                },
+                ..depth_params(&board, ASPIRATION_MIN_DEPTH)
+            };
+
+            let mut search = TestSearch::new();
+
+            search.run(board, &params);
+
+            let reports = search.reports();
+
+            let scores = reports
+                .iter()
+                .filter_map(|info| match info {
+                    SearchInformation::Summary(summary) => Some(summary.cp),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            let researches = reports
+                .iter()
+                .find_map(|info| match info {
+                    SearchInformation::AspirationResearches(researches) => Some(*researches),
+                    _ => None,
+                })
+                .unwrap();
+
+            assert!(scores[3] - scores[2] > ASPIRATION_MAX_WINDOW);
+
+            assert_eq!(researches, expected);
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use crate::INFINITY;
+use chess::ChessMove;
+use std::mem;
+
+pub const DEFAULT_HASH_SIZE_MB: usize = 16;
+pub const MIN_HASH_SIZE_MB: usize = 1;
+pub const MAX_HASH_SIZE_MB: usize = 1024;
+
+const BUCKET_SIZE: usize = 2;
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Bound {
+    #[default]
+    Exact, // the score is the true value of the position
+    Lower, // the search failed high, the true value is at least the score
+    Upper, // the search failed low, the true value is at most the score
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct TtEntry {
+    hash: u64,                        // full zobrist key
+    pub depth: u8,                    // remaining depth searched
+    pub score: i32,                   // score for the side to move
+    pub best_move: Option<ChessMove>, // best move found
+    pub bound: Bound,                 // how the score bounds the true value
+    generation: u8,                   // search the entry was written in
+}
+
+pub struct TranspositionTable {
+    entries: Vec<TtEntry>,
+    generation: u8, // bumped at the start of every search
+}
+
+impl TtEntry {
+    // whether the stored score settles the node for the given window without searching it
+    pub fn cutoff(&self, depth: u8, alpha: i32, beta: i32) -> Option<i32> {
+        if self.depth < depth {
+            return None;
+        }
+
+        match self.bound {
+            Bound::Exact => Some(self.score.clamp(alpha, beta)),
+            Bound::Lower if self.score >= beta => Some(beta),
+            Bound::Upper if self.score <= alpha => Some(alpha),
+            Bound::Lower | Bound::Upper => None,
+        }
+    }
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> Self {
+        let max_entries = (size_mb * 1024 * 1024 / mem::size_of::<TtEntry>()).max(BUCKET_SIZE);
+
+        // rounding down to a power of two number of buckets lets the index be a simple mask
+        // of the hash, at the cost of using less memory than asked for
+        let num_entries = 1 << max_entries.ilog2();
+
+        Self {
+            entries: vec![TtEntry::default(); num_entries],
+            generation: 0,
+        }
+    }
+
+    pub fn num_entries(&self) -> usize {
+        self.entries.len()
+    }
+
+    // in kilobytes, since the entries don't fill a whole number of megabytes
+    pub fn size_kb(&self) -> usize {
+        self.entries.len() * mem::size_of::<TtEntry>() / 1024
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(TtEntry::default());
+        self.generation = 0;
+    }
+
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    pub fn probe(&self, hash: u64, ply: u8) -> Option<TtEntry> {
+        let entry = self.bucket(hash).iter().find(|entry| entry.hash == hash)?;
+
+        Some(TtEntry {
+            score: score_from_tt(entry.score, ply),
+            ..*entry
+        })
+    }
+
+    pub fn store(
+        &mut self,
+        hash: u64,
+        depth: u8,
+        score: i32,
+        best_move: Option<ChessMove>,
+        bound: Bound,
+        ply: u8,
+    ) {
+        let bucket_start = self.bucket_start(hash);
+
+        let slot = match Self::replacement_slot(self.bucket(hash), hash, depth, self.generation) {
+            Some(slot) => slot,
+            None => return,
+        };
+
+        self.entries[bucket_start + slot] = TtEntry {
+            hash,
+            depth,
+            score: score_to_tt(score, ply),
+            best_move,
+            bound,
+            generation: self.generation,
+        };
+    }
+
+    // picks the slot in a bucket that a new entry should go into, or None if it should be
+    // dropped. the replacement rule is:
+    //  - an entry for the same position is only overwritten by a search at least as deep,
+    //    so a shallow re-search never throws away a deeper result, unless the entry is left
+    //    over from an earlier search
+    //  - otherwise an empty slot is used if there is one
+    //  - otherwise entries from earlier searches are evicted before ones from this search,
+    //    and the shallowest entry goes first, so deep entries survive the flood of shallow
+    //    nodes hashing to the same bucket while stale ones from old positions are recycled
+    fn replacement_slot(bucket: &[TtEntry], hash: u64, depth: u8, generation: u8) -> Option<usize> {
+        if let Some(slot) = bucket.iter().position(|entry| entry.hash == hash) {
+            let existing = bucket[slot];
+
+            return (existing.generation != generation || depth >= existing.depth).then_some(slot);
+        }
+
+        if let Some(slot) = bucket.iter().position(|entry| entry.hash == 0) {
+            return Some(slot);
+        }
+
+        bucket
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, entry)| (entry.generation == generation, entry.depth))
+            .map(|(slot, _)| slot)
+    }
+
+    fn bucket(&self, hash: u64) -> &[TtEntry] {
+        let start = self.bucket_start(hash);
+
+        &self.entries[start..start + BUCKET_SIZE]
+    }
+
+    fn bucket_start(&self, hash: u64) -> usize {
+        hash as usize & (self.entries.len() - 1) & !(BUCKET_SIZE - 1)
+    }
+}
+
+// mate scores are relative to the root, but an entry can be reached again at a different
+// ply, so they are stored relative to the position itself and converted back on probe
+fn score_to_tt(score: i32, ply: u8) -> i32 {
+    if score > INFINITY / 2 {
+        score + ply as i32
+    } else if score < -INFINITY / 2 {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: u8) -> i32 {
+    if score > INFINITY / 2 {
+        score - ply as i32
+    } else if score < -INFINITY / 2 {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(bound: Bound, depth: u8, score: i32) -> TtEntry {
+        TtEntry {
+            depth,
+            score,
+            bound,
+            ..TtEntry::default()
+        }
+    }
+
+    #[test]
+    fn cutoff_only_when_the_bound_settles_the_window() {
+        // an exact score settles any window, clamped into it
+        assert_eq!(entry(Bound::Exact, 4, 50).cutoff(4, 0, 100), Some(50));
+        assert_eq!(entry(Bound::Exact, 4, 150).cutoff(4, 0, 100), Some(100));
+        assert_eq!(entry(Bound::Exact, 4, -50).cutoff(4, 0, 100), Some(0));
+
+        // a lower bound only settles a node that fails high
+        assert_eq!(entry(Bound::Lower, 4, 100).cutoff(4, 0, 100), Some(100));
+        assert_eq!(entry(Bound::Lower, 4, 99).cutoff(4, 0, 100), None);
+
+        // an upper bound only settles a node that fails low
+        assert_eq!(entry(Bound::Upper, 4, 0).cutoff(4, 0, 100), Some(0));
+        assert_eq!(entry(Bound::Upper, 4, 1).cutoff(4, 0, 100), None);
+
+        // a shallower search than the one asked for settles nothing, a deeper one does
+        assert_eq!(entry(Bound::Exact, 3, 50).cutoff(4, 0, 100), None);
+        assert_eq!(entry(Bound::Lower, 3, 200).cutoff(4, 0, 100), None);
+        assert_eq!(entry(Bound::Upper, 3, -200).cutoff(4, 0, 100), None);
+        assert_eq!(entry(Bound::Lower, 8, 200).cutoff(4, 0, 100), Some(100));
+    }
+
+    // an entry as the table keeps it, for building buckets by hand
+    fn stored(hash: u64, depth: u8, generation: u8) -> TtEntry {
+        TtEntry {
+            hash,
+            depth,
+            generation,
+            ..TtEntry::default()
+        }
+    }
+
+    #[test]
+    fn same_position_is_only_replaced_by_a_deeper_search_or_a_newer_one() {
+        let bucket = [stored(7, 6, 1), TtEntry::default()];
+
+        assert_eq!(TranspositionTable::replacement_slot(&bucket, 7, 5, 1), None);
+        assert_eq!(
+            TranspositionTable::replacement_slot(&bucket, 7, 6, 1),
+            Some(0)
+        );
+        assert_eq!(
+            TranspositionTable::replacement_slot(&bucket, 7, 9, 1),
+            Some(0)
+        );
+
+        // left over from an earlier search, so even a shallow result replaces it
+        assert_eq!(
+            TranspositionTable::replacement_slot(&bucket, 7, 1, 2),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn empty_slots_are_used_before_anything_is_evicted() {
+        let bucket = [stored(7, 1, 0), TtEntry::default()];
+
+        assert_eq!(
+            TranspositionTable::replacement_slot(&bucket, 8, 9, 1),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn old_then_shallow_entries_are_evicted_first() {
+        // an old entry goes before a current one, however deep it is
+        let bucket = [stored(7, 2, 1), stored(8, 20, 0)];
+
+        assert_eq!(
+            TranspositionTable::replacement_slot(&bucket, 9, 1, 1),
+            Some(1)
+        );
+
+        // among entries of the same search the shallowest goes
+        let bucket = [stored(7, 8, 1), stored(8, 3, 1)];
+
+        assert_eq!(
+            TranspositionTable::replacement_slot(&bucket, 9, 1, 1),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn deep_entries_survive_colliding_shallow_ones() {
+        let mut tt = TranspositionTable::new(MIN_HASH_SIZE_MB);
+
+        // the same low bits put all of these in one bucket, the high bits tell them apart
+        let hash = |key: u64| (key << 40) | 0x1234;
+
+        tt.new_search();
+
+        tt.store(hash(1), 10, 0, None, Bound::Exact, 0);
+
+        for key in 2..50 {
+            tt.store(hash(key), 1, 0, None, Bound::Exact, 0);
+        }
+
+        assert_eq!(tt.probe(hash(1), 0).unwrap().depth, 10);
+        assert_eq!(tt.probe(hash(49), 0).unwrap().depth, 1);
+        assert!(tt.probe(hash(48), 0).is_none());
+
+        // in the next search it's only stale, and goes before the shallow entry just stored
+        tt.new_search();
+
+        tt.store(hash(50), 1, 0, None, Bound::Exact, 0);
+        tt.store(hash(51), 1, 0, None, Bound::Exact, 0);
+
+        assert!(tt.probe(hash(1), 0).is_none());
+        assert!(tt.probe(hash(50), 0).is_some());
+        assert!(tt.probe(hash(51), 0).is_some());
+    }
+
+    #[test]
+    fn stores_and_probes_bounds() {
+        let mut tt = TranspositionTable::new(MIN_HASH_SIZE_MB);
+
+        tt.store(12345, 5, 30, None, Bound::Upper, 0);
+
+        let entry = tt.probe(12345, 0).unwrap();
+
+        assert_eq!(entry.depth, 5);
+        assert_eq!(entry.score, 30);
+        assert!(entry.bound == Bound::Upper);
+
+        assert!(tt.probe(54321, 0).is_none());
+    }
+
+    #[test]
+    fn reports_the_size_actually_used() {
+        let entry_size = mem::size_of::<TtEntry>();
+
+        for size_mb in [MIN_HASH_SIZE_MB, DEFAULT_HASH_SIZE_MB] {
+            let tt = TranspositionTable::new(size_mb);
+
+            // never more than asked for, and more than half of it
+            assert!(tt.size_kb() <= size_mb * 1024);
+            assert!(tt.size_kb() > size_mb * 1024 / 2);
+            assert_eq!(tt.size_kb(), tt.num_entries() * entry_size / 1024);
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use crate::evaluation::{evaluate_position, material_and_psqt, EvalWeights, PawnHashTable};
+use chess::{Board, Color};
+use std::fs;
+use std::str::FromStr;
+
+const MAX_PASSES: usize = 100; // local search passes over every weight before giving up
+const K_STEPS: [f64; 3] = [0.1, 0.01, 0.001]; // ever finer steps of the scaling constant search
+
+struct TuningPosition {
+    board: Board,
+    result: f64, // 1 for a white win, 0.5 for a draw, 0 for a black win
+}
+
+// texel tuning: the weights are nudged one at a time for as long as that lowers the mean
+// squared error between the game results and the win probability predicted from the static
+// eval. the positions should be quiet, since there is no search to resolve captures. the
+// weights are written to the output file after every pass, in the format EvalFile reads
+pub fn tune(
+    positions_path: &str,
+    output_path: &str,
+    mut weights: EvalWeights,
+    report: impl Fn(String),
+) -> Result<(), String> {
+    let positions = load_positions(positions_path)?;
+
+    report(format!("loaded {} positions", positions.len()));
+
+    let pawn_table = &mut PawnHashTable::new();
+
+    let k = best_scaling_constant(&positions, &weights, pawn_table);
+
+    let mut best_error = mean_squared_error(&positions, &weights, k, pawn_table);
+
+    report(format!("k: {k:.3}, starting error: {best_error:.6}"));
+
+    for pass in 1..=MAX_PASSES {
+        let mut improved = false;
+
+        for parameter in 0..EvalWeights::PARAMETERS {
+            for step in [1, -1] {
+                *weights.parameter_mut(parameter) += step;
+
+                let error = mean_squared_error(&positions, &weights, k, pawn_table);
+
+                if error < best_error {
+                    best_error = error;
+                    improved = true;
+
+                    break;
+                }
+
+                *weights.parameter_mut(parameter) -= step;
+            }
+        }
+
+        fs::write(output_path, weights.to_string()).map_err(|err| err.to_string())?;
+
+        report(format!("pass {pass}, error: {best_error:.6}"));
+
+        if !improved {
+            break;
+        }
+    }
+
+    report(format!(
+        "final error: {best_error:.6}, weights written to {output_path}"
+    ));
+
+    Ok(())
+}
+
+// each line is a fen followed by the result, as 1.0, 0.5 or 0.0 or as 1-0, 1/2-1/2 or 0-1,
+// optionally in brackets or quotes
+fn load_positions(path: &str) -> Result<Vec<TuningPosition>, String> {
+    let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+
+    let mut positions = Vec::new();
+
+    for (line_number, line) in contents.lines().enumerate() {
+        let line_number = line_number + 1;
+
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let (fen, result) = line
+            .rsplit_once(char::is_whitespace)
+            .ok_or_else(|| format!("line {line_number}: expected a fen and a result"))?;
+
+        let result = match result.trim_matches(|c| "[]\";".contains(c)) {
+            "1.0" | "1-0" => 1.0,
+            "0.5" | "1/2-1/2" => 0.5,
+            "0.0" | "0-1" => 0.0,
+            other => return Err(format!("line {line_number}: invalid result {other}")),
+        };
+
+        let board = Board::from_str(fen.trim())
+            .map_err(|err| format!("line {line_number}: invalid fen: {err}"))?;
+
+        positions.push(TuningPosition { board, result });
+    }
+
+    if positions.is_empty() {
+        return Err("no positions to tune on".to_string());
+    }
+
+    Ok(positions)
+}
+
+// the k that best maps evals to results for the current weights, so tuning changes the weights
+// rather than just their overall scale
+fn best_scaling_constant(
+    positions: &[TuningPosition],
+    weights: &EvalWeights,
+    pawn_table: &mut PawnHashTable,
+) -> f64 {
+    let mut best_k = 1.0;
+    let mut best_error = mean_squared_error(positions, weights, best_k, pawn_table);
+
+    for step in K_STEPS {
+        let centre = best_k;
+
+        for i in -10..=10 {
+            let k = centre + step * i as f64;
+
+            if k <= 0.0 {
+                continue;
+            }
+
+            let error = mean_squared_error(positions, weights, k, pawn_table);
+
+            if error < best_error {
+                best_k = k;
+                best_error = error;
+            }
+        }
+    }
+
+    best_k
+}
+
+fn mean_squared_error(
+    positions: &[TuningPosition],
+    weights: &EvalWeights,
+    k: f64,
+    pawn_table: &mut PawnHashTable,
+) -> f64 {
+    let total = positions
+        .iter()
+        .map(|position| {
+            let board = &position.board;
+
+            let psqt = material_and_psqt(board, weights);
+
+            let eval = match board.side_to_move() {
+                Color::White => evaluate_position(board, psqt, 0, pawn_table),
+                Color::Black => -evaluate_position(board, psqt, 0, pawn_table),
+            };
+
+            let predicted = 1.0 / (1.0 + 10f64.powf(-k * eval as f64 / 400.0));
+
+            (position.result - predicted).powi(2)
+        })
+        .sum::<f64>();
+
+    total / positions.len() as f64
+}
@@ -1,10 +1,22 @@
+use crate::chess960::CastlingRooks;
+use crate::epd::{EpdLimit, DEFAULT_EPD_MOVE_TIME};
+use crate::evaluation::{EvalBreakdown, EvalWeights};
 use crate::search::SearchSummary;
-use crate::{Information, INFINITY};
-use chess::ChessMove;
+use crate::transposition::{DEFAULT_HASH_SIZE_MB, MAX_HASH_SIZE_MB, MIN_HASH_SIZE_MB};
+use crate::{
+    Information, DEFAULT_ASPIRATION_GROWTH, DEFAULT_ASPIRATION_WINDOW, DEFAULT_MOVE_OVERHEAD,
+    INFINITY, MAX_ASPIRATION_GROWTH, MAX_ASPIRATION_WINDOW, MAX_CONTEMPT,
+    MAX_MINIMUM_THINKING_TIME, MAX_MOVE_OVERHEAD, MAX_PIECE_VALUE, MIN_ASPIRATION_GROWTH,
+    PIECE_VALUE_OPTIONS,
+};
+use chess::{Board, ChessMove, Color, Square, ALL_FILES, ALL_RANKS};
 use crossbeam_channel::Sender;
+use std::str::FromStr;
 use std::thread::{self, JoinHandle};
 use std::time::Duration;
-use vampirc_uci::{parse, UciInfoAttribute, UciMessage, UciTimeControl};
+use vampirc_uci::{parse, UciInfoAttribute, UciMessage, UciOptionConfig, UciTimeControl};
+
+const STARTPOS_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
 pub struct Uci {
     control_handle: Option<JoinHandle<()>>,
@@ -12,6 +24,12 @@ pub struct Uci {
     control_tx: Option<Sender<UciControl>>,
 }
 
+impl Default for Uci {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Uci {
     pub fn new() -> Self {
         Self {
@@ -21,6 +39,14 @@ impl Uci {
         }
     }
 
+    // output goes to the given channel instead of being printed, so tests can read it back
+    pub fn with_output(control_tx: Sender<UciControl>) -> Self {
+        Self {
+            control_tx: Some(control_tx),
+            ..Self::new()
+        }
+    }
+
     pub fn init(&mut self, report_tx: Sender<Information>) {
         self.report_thread(report_tx);
         self.control_thread();
@@ -32,6 +58,16 @@ impl Uci {
         }
     }
 
+    // waits for both threads to finish after a quit, so everything sent before it is printed
+    pub fn wait(&mut self) {
+        for handle in [self.report_handle.take(), self.control_handle.take()]
+            .into_iter()
+            .flatten()
+        {
+            handle.join().unwrap();
+        }
+    }
+
     fn report_thread(&mut self, report_tx: Sender<Information>) {
         let mut incoming_data = String::new();
 
@@ -39,79 +75,17 @@ impl Uci {
             let mut quit = false;
 
             while !quit {
-                std::io::stdin().read_line(&mut incoming_data).unwrap();
-
-                let msgs = parse(&incoming_data);
-
-                for msg in msgs {
-                    let report = match msg {
-                        vampirc_uci::UciMessage::Uci => UciReport::Uci,
-
-                        vampirc_uci::UciMessage::Debug(debug) => UciReport::Debug(debug),
-
-                        vampirc_uci::UciMessage::IsReady => UciReport::IsReady,
-
-                        vampirc_uci::UciMessage::Position {
-                            startpos,
-                            fen,
-                            moves,
-                        } => {
-                            let fen = if startpos {
-                                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
-                                    .to_string()
-                            } else {
-                                fen.unwrap().to_string()
-                            };
+                // the gui closing the stream means it's gone, so shut down as if it said quit
+                if let Ok(0) | Err(_) = std::io::stdin().read_line(&mut incoming_data) {
+                    report_tx
+                        .send(Information::UciInformation(UciReport::Quit))
+                        .unwrap();
 
-                            UciReport::Position(fen, moves)
-                        }
-
-                        UciMessage::UciNewGame => UciReport::UciNewGame,
-
-                        UciMessage::Stop => UciReport::Stop,
-
-                        UciMessage::Quit => {
-                            quit = true;
-                            UciReport::Quit
-                        }
-
-                        UciMessage::Go {
-                            time_control,
-                            search_control,
-                        } => match (time_control, search_control) {
-                            (Some(tc), None) => match tc {
-                                UciTimeControl::Ponder => panic!("ponder not supported"),
-
-                                UciTimeControl::Infinite => UciReport::GoInfinite,
-
-                                UciTimeControl::TimeLeft {
-                                    white_time,
-                                    black_time,
-                                    white_increment,
-                                    black_increment,
-                                    moves_to_go,
-                                } => UciReport::GoGameTime(GameTime {
-                                    wtime: white_time
-                                        .map(|t| t.to_std().unwrap_or(Duration::from_secs(0))),
-                                    btime: black_time
-                                        .map(|t| t.to_std().unwrap_or(Duration::from_secs(0))),
-                                    winc: white_increment
-                                        .map(|t| t.to_std().unwrap_or(Duration::from_secs(0))),
-                                    binc: black_increment
-                                        .map(|t| t.to_std().unwrap_or(Duration::from_secs(0))),
-                                    mtg: moves_to_go,
-                                }),
-
-                                UciTimeControl::MoveTime(movetime) => UciReport::GoMoveTime(
-                                    movetime.to_std().unwrap_or(Duration::from_secs(0)),
-                                ),
-                            },
-                            (None, Some(_sc)) => todo!(),
-                            _ => unreachable!(),
-                        },
+                    break;
+                }
 
-                        _ => UciReport::Unknown,
-                    };
+                for report in parse_reports(&incoming_data) {
+                    quit |= matches!(report, UciReport::Quit);
 
                     report_tx.send(Information::UciInformation(report)).unwrap();
                 }
@@ -136,13 +110,132 @@ impl Uci {
                     UciControl::Identify => {
                         println!("{}", UciMessage::id_name("kychess"));
                         println!("{}", UciMessage::id_author("skycloudd"));
+
+                        println!(
+                            "{}",
+                            UciMessage::Option(UciOptionConfig::Check {
+                                name: "Quiescence".to_string(),
+                                default: Some(true),
+                            })
+                        );
+
+                        println!(
+                            "{}",
+                            UciMessage::Option(UciOptionConfig::Spin {
+                                name: "Minimum Thinking Time".to_string(),
+                                default: Some(0),
+                                min: Some(0),
+                                max: Some(MAX_MINIMUM_THINKING_TIME as i64),
+                            })
+                        );
+
+                        println!(
+                            "{}",
+                            UciMessage::Option(UciOptionConfig::Spin {
+                                name: "Move Overhead".to_string(),
+                                default: Some(DEFAULT_MOVE_OVERHEAD as i64),
+                                min: Some(0),
+                                max: Some(MAX_MOVE_OVERHEAD as i64),
+                            })
+                        );
+
+                        println!(
+                            "{}",
+                            UciMessage::Option(UciOptionConfig::Check {
+                                name: "Ponder".to_string(),
+                                default: Some(false),
+                            })
+                        );
+
+                        println!(
+                            "{}",
+                            UciMessage::Option(UciOptionConfig::Check {
+                                name: "UCI_Chess960".to_string(),
+                                default: Some(false),
+                            })
+                        );
+
+                        println!(
+                            "{}",
+                            UciMessage::Option(UciOptionConfig::Spin {
+                                name: "Contempt".to_string(),
+                                default: Some(0),
+                                min: Some(-MAX_CONTEMPT as i64),
+                                max: Some(MAX_CONTEMPT as i64),
+                            })
+                        );
+
+                        println!(
+                            "{}",
+                            UciMessage::Option(UciOptionConfig::Spin {
+                                name: "Aspiration Window".to_string(),
+                                default: Some(DEFAULT_ASPIRATION_WINDOW as i64),
+                                min: Some(1),
+                                max: Some(MAX_ASPIRATION_WINDOW as i64),
+                            })
+                        );
+
+                        println!(
+                            "{}",
+                            UciMessage::Option(UciOptionConfig::Spin {
+                                name: "Aspiration Growth".to_string(),
+                                default: Some(DEFAULT_ASPIRATION_GROWTH as i64),
+                                min: Some(MIN_ASPIRATION_GROWTH as i64),
+                                max: Some(MAX_ASPIRATION_GROWTH as i64),
+                            })
+                        );
+
+                        println!(
+                            "{}",
+                            UciMessage::Option(UciOptionConfig::Spin {
+                                name: "Hash".to_string(),
+                                default: Some(DEFAULT_HASH_SIZE_MB as i64),
+                                min: Some(MIN_HASH_SIZE_MB as i64),
+                                max: Some(MAX_HASH_SIZE_MB as i64),
+                            })
+                        );
+
+                        let weights = EvalWeights::default();
+
+                        for (name, piece) in PIECE_VALUE_OPTIONS {
+                            println!(
+                                "{}",
+                                UciMessage::Option(UciOptionConfig::Spin {
+                                    name: name.to_string(),
+                                    default: Some(weights.piece_value(piece) as i64),
+                                    min: Some(0),
+                                    max: Some(MAX_PIECE_VALUE as i64),
+                                })
+                            );
+                        }
+
+                        println!(
+                            "{}",
+                            UciMessage::Option(UciOptionConfig::String {
+                                name: "EvalFile".to_string(),
+                                default: Some("<empty>".to_string()),
+                            })
+                        );
+
+                        println!(
+                            "{}",
+                            UciMessage::Option(UciOptionConfig::Button {
+                                name: "Clear Hash".to_string(),
+                            })
+                        );
+
                         println!("{}", UciMessage::UciOk);
                     }
                     UciControl::Ready => println!("{}", UciMessage::ReadyOk),
                     UciControl::Quit => quit = true,
-                    UciControl::BestMove(bm) => {
-                        println!("{}", UciMessage::best_move(bm));
-                    }
+                    UciControl::BestMove(bm, ponder) => match (bm, ponder) {
+                        (Some(bm), Some(ponder)) => {
+                            println!("{}", UciMessage::best_move_with_ponder(bm, ponder))
+                        }
+                        (Some(bm), None) => println!("{}", UciMessage::best_move(bm)),
+                        // checkmate or stalemate at the root, which uci sends as the null move
+                        (None, _) => println!("bestmove 0000"),
+                    },
                     UciControl::SearchSummary(summary) => {
                         let attrs = vec![
                             UciInfoAttribute::Depth(summary.depth),
@@ -150,25 +243,19 @@ impl Uci {
                             UciInfoAttribute::Time(
                                 vampirc_uci::Duration::from_std(summary.time).unwrap(),
                             ),
-                            if summary.cp.abs() > INFINITY / 2 {
-                                let mate_in_plies = INFINITY - summary.cp.abs();
-                                let sign = summary.cp.signum();
-
-                                let mate_in_moves = mate_in_plies / 2 + mate_in_plies % 2;
-
-                                UciInfoAttribute::Score {
+                            match mate_in_moves(summary.cp) {
+                                Some(mate) => UciInfoAttribute::Score {
                                     cp: None,
-                                    mate: Some((sign * mate_in_moves) as i8),
+                                    mate: Some(mate),
                                     lower_bound: None,
                                     upper_bound: None,
-                                }
-                            } else {
-                                UciInfoAttribute::Score {
+                                },
+                                None => UciInfoAttribute::Score {
                                     cp: Some(summary.cp),
                                     mate: None,
                                     lower_bound: None,
                                     upper_bound: None,
-                                }
+                                },
                             },
                             UciInfoAttribute::Nodes(summary.nodes),
                             UciInfoAttribute::Nps(summary.nps),
@@ -177,9 +264,48 @@ impl Uci {
 
                         println!("{}", UciMessage::Info(attrs));
                     }
+                    UciControl::CurrMove(mov, number) => {
+                        // vampirc serializes the number as "currmovenum", not as the spec says
+                        println!("info currmove {mov} currmovenumber {number}");
+                    }
                     UciControl::Info(info) => {
                         println!("{}", UciMessage::info_string(info));
                     }
+                    UciControl::Display {
+                        board,
+                        castling_rooks,
+                        halfmove_clock,
+                        fullmove_number,
+                        time_used,
+                    } => {
+                        print_board(&board, castling_rooks, halfmove_clock, fullmove_number);
+
+                        println!("Time used this game: {} ms", time_used.as_millis());
+                    }
+                    UciControl::EvalBreakdown(breakdown) => println!("{breakdown}"),
+                    UciControl::Eval {
+                        white,
+                        side_to_move,
+                    } => {
+                        println!("White eval:        {white:+}");
+                        println!("Side to move eval: {side_to_move:+}");
+                    }
+                    UciControl::Perft {
+                        divide,
+                        nodes,
+                        time,
+                    } => {
+                        let nps = (nodes as f64 / time.as_secs_f64()) as u64;
+
+                        for (mov, nodes) in divide {
+                            println!("{mov}: {nodes}");
+                        }
+
+                        println!();
+                        println!("Nodes searched: {nodes}");
+                        println!("Time: {} ms", time.as_millis());
+                        println!("Nodes per second: {nps}");
+                    }
                 }
             }
         });
@@ -189,18 +315,273 @@ impl Uci {
     }
 }
 
+// a diagram with white at the bottom, followed by the fen and the zobrist hash
+fn print_board(
+    board: &Board,
+    castling_rooks: Option<CastlingRooks>,
+    halfmove_clock: u16,
+    fullmove_number: u16,
+) {
+    let separator = "+---".repeat(8) + "+";
+
+    for rank in ALL_RANKS.iter().rev() {
+        println!(" {separator}");
+
+        let squares = ALL_FILES
+            .iter()
+            .map(|&file| {
+                let square = Square::make_square(*rank, file);
+
+                match (board.piece_on(square), board.color_on(square)) {
+                    (Some(piece), Some(colour)) => piece.to_string(colour),
+                    _ => " ".to_string(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" | ");
+
+        println!(" | {squares} | {}", rank.to_index() + 1);
+    }
+
+    println!(" {separator}");
+
+    let files = ALL_FILES
+        .iter()
+        .map(|file| ((b'a' + file.to_index() as u8) as char).to_string())
+        .collect::<Vec<_>>()
+        .join("   ");
+
+    println!("   {files}");
+    println!();
+
+    // the board doesn't keep the move counters, so they come from the engine, which follows them
+    // from the fen through the moves played after it
+    let fen = board.to_string();
+    let mut placement = fen.split(' ').take(4).collect::<Vec<_>>();
+
+    // in chess960 the castling rights are kept by the engine rather than the board
+    let castling = castling_rooks.map(|castling_rooks| castling_rooks.to_fen());
+
+    if let Some(castling) = &castling {
+        placement[2] = castling;
+    }
+
+    let placement = placement.join(" ");
+
+    let hash = board.get_hash() ^ castling_rooks.map_or(0, |castling_rooks| castling_rooks.hash());
+
+    let side_to_move = match board.side_to_move() {
+        Color::White => "white",
+        Color::Black => "black",
+    };
+
+    println!("Fen: {placement} {halfmove_clock} {fullmove_number}");
+    println!("Key: {hash:016X}");
+    println!("Side to move: {side_to_move}");
+}
+
+// the search scores a mate as INFINITY - ply, where ply is the distance from the root to
+// the checkmated position, and as -(INFINITY - ply) when the engine is the one getting mated.
+// uci counts whole moves of the side to move: mating on our nth move takes 2n - 1 plies,
+// being mated on the opponent's nth move takes 2n plies and is reported as a negative value
+pub fn mate_in_moves(cp: i32) -> Option<i8> {
+    if cp.abs() <= INFINITY / 2 {
+        return None;
+    }
+
+    let mate_in_plies = INFINITY - cp.abs();
+
+    let mate_in_moves = if cp > 0 {
+        (mate_in_plies + 1) / 2
+    } else {
+        -(mate_in_plies / 2)
+    };
+
+    Some(mate_in_moves as i8)
+}
+
+// the reports for a line from the gui, most of which vampirc parses
+fn parse_reports(input: &str) -> Vec<UciReport> {
+    if let Some(report) = parse_custom(input) {
+        return vec![report];
+    }
+
+    // vampirc doesn't recognise a bare go followed by a newline, so the line ending is trimmed
+    // off first
+    let msgs = parse(input.trim_end());
+
+    // vampirc drops the ponder flag when the clocks are given as well, so look for it in the
+    // command itself
+    let ponder = input.trim_start().starts_with("go")
+        && input.split_whitespace().any(|token| token == "ponder");
+
+    msgs.into_iter()
+        .map(|msg| match msg {
+            vampirc_uci::UciMessage::Uci => UciReport::Uci,
+
+            vampirc_uci::UciMessage::Debug(debug) => UciReport::Debug(debug),
+
+            vampirc_uci::UciMessage::IsReady => UciReport::IsReady,
+
+            UciMessage::SetOption { name, value } => UciReport::SetOption(name, value),
+
+            UciMessage::UciNewGame => UciReport::UciNewGame,
+
+            UciMessage::Register { .. } => UciReport::Register,
+
+            UciMessage::Stop => UciReport::Stop,
+
+            UciMessage::PonderHit => UciReport::PonderHit,
+
+            UciMessage::Quit => UciReport::Quit,
+
+            UciMessage::Go {
+                time_control,
+                search_control,
+            } => {
+                // node and mate limits aren't supported, so they're searched without a limit
+                // other than the time control
+                let search_control = search_control
+                    .map(|sc| SearchControl {
+                        depth: sc.depth,
+                        search_moves: sc.search_moves,
+                    })
+                    .unwrap_or_default();
+
+                match time_control {
+                    Some(UciTimeControl::Ponder) => {
+                        UciReport::GoPonder(GameTime::default(), search_control)
+                    }
+
+                    Some(UciTimeControl::Infinite) | None => UciReport::GoInfinite(search_control),
+
+                    Some(UciTimeControl::TimeLeft {
+                        white_time,
+                        black_time,
+                        white_increment,
+                        black_increment,
+                        moves_to_go,
+                    }) => {
+                        let game_time = GameTime {
+                            wtime: white_time.map(|t| t.to_std().unwrap_or(Duration::from_secs(0))),
+                            btime: black_time.map(|t| t.to_std().unwrap_or(Duration::from_secs(0))),
+                            winc: white_increment
+                                .map(|t| t.to_std().unwrap_or(Duration::from_secs(0))),
+                            binc: black_increment
+                                .map(|t| t.to_std().unwrap_or(Duration::from_secs(0))),
+                            mtg: moves_to_go,
+                        };
+
+                        if ponder {
+                            UciReport::GoPonder(game_time, search_control)
+                        } else {
+                            UciReport::GoGameTime(game_time, search_control)
+                        }
+                    }
+
+                    Some(UciTimeControl::MoveTime(movetime)) => UciReport::GoMoveTime(
+                        movetime.to_std().unwrap_or(Duration::from_secs(0)),
+                        search_control,
+                    ),
+                }
+            }
+
+            _ => UciReport::Unknown,
+        })
+        .collect()
+}
+
+// vampirc only takes KQkq in a fen's castling field, so chess960 positions that give the files
+// of the rooks instead couldn't be set up through it
+fn parse_position<'a>(mut tokens: impl Iterator<Item = &'a str>) -> Option<UciReport> {
+    let startpos = match tokens.next()? {
+        "startpos" => true,
+        "fen" => false,
+        _ => return None,
+    };
+
+    let fen = tokens
+        .by_ref()
+        .take_while(|&token| token != "moves")
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let fen = match (startpos, fen.is_empty()) {
+        (true, true) => STARTPOS_FEN.to_string(),
+        (false, false) => fen,
+        _ => return None,
+    };
+
+    // the moves before one that doesn't parse are still played, and the bad one is passed on
+    // so it can be reported, like an illegal move would be
+    let mut moves = Vec::new();
+    let mut unparsed = None;
+
+    for token in tokens {
+        match ChessMove::from_str(token) {
+            Ok(mov) => moves.push(mov),
+            Err(_) => {
+                unparsed = Some(token.to_string());
+
+                break;
+            }
+        }
+    }
+
+    Some(UciReport::Position(fen, moves, unparsed))
+}
+
+fn parse_custom(input: &str) -> Option<UciReport> {
+    let mut tokens = input.split_whitespace();
+
+    match tokens.next()? {
+        "d" => Some(UciReport::Display),
+        "position" => parse_position(tokens),
+        "epd" => {
+            let path = tokens.next()?.to_string();
+
+            let limit = match tokens.next() {
+                None => EpdLimit::MoveTime(DEFAULT_EPD_MOVE_TIME),
+                Some("depth") => EpdLimit::Depth(tokens.next()?.parse().ok()?),
+                Some("movetime") => {
+                    EpdLimit::MoveTime(Duration::from_millis(tokens.next()?.parse().ok()?))
+                }
+                Some(_) => return None,
+            };
+
+            Some(UciReport::Epd(path, limit))
+        }
+        "eval" => Some(UciReport::Eval),
+        "perft" => tokens.next()?.parse().ok().map(UciReport::Perft),
+        "tune" => Some(UciReport::Tune(
+            tokens.next()?.to_string(),
+            tokens.next()?.to_string(),
+        )),
+        _ => None,
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum UciReport {
     Uci,
     Debug(bool),
     IsReady,
-    Position(String, Vec<ChessMove>),
+    Position(String, Vec<ChessMove>, Option<String>), // fen, moves, a move that didn't parse
+    SetOption(String, Option<String>),
     UciNewGame,
+    Register,
     Stop,
+    PonderHit,
     Quit,
-    GoInfinite,
-    GoMoveTime(Duration),
-    GoGameTime(GameTime),
+    GoInfinite(SearchControl),
+    GoPonder(GameTime, SearchControl),
+    GoMoveTime(Duration, SearchControl),
+    GoGameTime(GameTime, SearchControl),
+    Display,
+    Epd(String, EpdLimit), // suite file, search limit per position
+    Eval,
+    Perft(u8),            // depth
+    Tune(String, String), // positions file, output file
     Unknown,
 }
 
@@ -213,11 +594,125 @@ pub struct GameTime {
     pub mtg: Option<u8>,
 }
 
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SearchControl {
+    pub depth: Option<u8>,
+    pub search_moves: Vec<ChessMove>,
+}
+
 pub enum UciControl {
     Identify,
     Ready,
     Quit,
-    BestMove(ChessMove),
+    BestMove(Option<ChessMove>, Option<ChessMove>),
     SearchSummary(SearchSummary),
+    CurrMove(ChessMove, u16),
     Info(String),
+    Display {
+        board: Board,
+        castling_rooks: Option<CastlingRooks>, // the castling rights in chess960
+        halfmove_clock: u16,
+        fullmove_number: u16,
+        time_used: Duration, // on the engine's clock in this game
+    },
+    EvalBreakdown(EvalBreakdown),
+    Eval {
+        white: i32,
+        side_to_move: i32,
+    },
+    Perft {
+        divide: Vec<(ChessMove, u64)>, // nodes below each root move
+        nodes: u64,
+        time: Duration,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mate_scores_are_reported_in_moves() {
+        // mating with our first move is one ply, with our second is three
+        assert_eq!(mate_in_moves(INFINITY - 1), Some(1));
+        assert_eq!(mate_in_moves(INFINITY - 3), Some(2));
+        assert_eq!(mate_in_moves(INFINITY - 4), Some(2));
+
+        // being mated by the opponent's first move is two plies, by their second is four
+        assert_eq!(mate_in_moves(-(INFINITY - 2)), Some(-1));
+        assert_eq!(mate_in_moves(-(INFINITY - 4)), Some(-2));
+
+        assert_eq!(mate_in_moves(0), None);
+        assert_eq!(mate_in_moves(2500), None);
+        assert_eq!(mate_in_moves(-2500), None);
+    }
+
+    #[test]
+    fn parses_go_ponder() {
+        // with or without clocks, whether or not the ponder option is on
+        for command in ["go ponder\n", "go ponder wtime 1000 btime 2000\n"] {
+            match parse_reports(command).as_slice() {
+                [UciReport::GoPonder(_, _)] => (),
+                _ => panic!("{command} not parsed as go ponder"),
+            }
+        }
+
+        match parse_reports("go ponder wtime 1000 btime 2000 winc 10\n").as_slice() {
+            [UciReport::GoPonder(game_time, _)] => {
+                assert_eq!(game_time.wtime, Some(Duration::from_millis(1000)));
+                assert_eq!(game_time.btime, Some(Duration::from_millis(2000)));
+                assert_eq!(game_time.winc, Some(Duration::from_millis(10)));
+            }
+            _ => panic!("go ponder with clocks not parsed"),
+        }
+    }
+
+    #[test]
+    fn parses_perft_depth() {
+        assert!(matches!(
+            parse_custom("perft 4\n"),
+            Some(UciReport::Perft(4))
+        ));
+        assert!(parse_custom("perft\n").is_none());
+        assert!(parse_custom("perft deep\n").is_none());
+    }
+
+    #[test]
+    fn parses_positions_vampirc_rejects() {
+        let fen = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+
+        match parse_custom(&format!("position fen {fen} moves g1h1 e7e8q\n")) {
+            Some(UciReport::Position(parsed, moves, None)) => {
+                assert_eq!(parsed, fen);
+                assert_eq!(
+                    moves,
+                    vec![
+                        ChessMove::new(Square::G1, Square::H1, None),
+                        ChessMove::new(Square::E7, Square::E8, Some(chess::Piece::Queen)),
+                    ]
+                );
+            }
+            _ => panic!("position not parsed"),
+        }
+
+        match parse_custom("position startpos\n") {
+            Some(UciReport::Position(parsed, moves, None)) => {
+                assert_eq!(parsed, STARTPOS_FEN);
+                assert!(moves.is_empty());
+            }
+            _ => panic!("position not parsed"),
+        }
+
+        assert!(parse_custom("position fen moves e2e4\n").is_none());
+
+        // the moves up to one that doesn't parse are kept, and it's passed on to be reported
+        match parse_custom("position startpos moves e2e4 e7 g1f3\n") {
+            Some(UciReport::Position(parsed, moves, unparsed)) => {
+                assert_eq!(parsed, STARTPOS_FEN);
+                assert_eq!(moves, vec![ChessMove::new(Square::E2, Square::E4, None)]);
+                assert_eq!(unparsed.as_deref(), Some("e7"));
+            }
+            _ => panic!("position not parsed"),
+        }
+    }
 }
@@ -0,0 +1,50 @@
+use chess::{Board, ChessMove};
+use kittycat::search::Search;
+use kittycat::INFINITY;
+use std::str::FromStr;
+
+// deep enough for every position here, and quick in a debug build
+const DEPTH: u8 = 6;
+
+fn mov(uci: &str) -> ChessMove {
+    ChessMove::from_str(uci).unwrap()
+}
+
+// the best move and score of a search to DEPTH
+fn search(fen: &str) -> (Option<ChessMove>, i32) {
+    let (best_move, summary) = Search::run_to_depth(Board::from_str(fen).unwrap(), DEPTH);
+
+    (best_move, summary.unwrap().cp)
+}
+
+#[test]
+fn finds_forced_mates() {
+    for (fen, solution, mate_in_plies) in [
+        // 1. Nf6+ gxf6 2. Bxf7#
+        (
+            "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 10",
+            "d5f6",
+            3,
+        ),
+        // 1... Bc5+ 2. Kxc5 Qb6+ 3. Kd5 Qd6#
+        (
+            "r1b1kb1r/pppp1ppp/5q2/4n3/3KP3/2N3PN/PPP4P/R1BQ1B1R b kq - 0 1",
+            "f8c5",
+            5,
+        ),
+    ] {
+        let (best_move, score) = search(fen);
+
+        assert_eq!(best_move, Some(mov(solution)), "{fen}");
+        assert_eq!(score, INFINITY - mate_in_plies, "{fen}");
+    }
+}
+
+#[test]
+fn finds_a_fork_that_wins_the_queen() {
+    // Nc7+ forks the king and the queen, which is then taken for the knight
+    let (best_move, score) = search("q3k3/pp6/8/1N6/8/8/5PPP/6K1 w - - 0 1");
+
+    assert_eq!(best_move, Some(mov("b5c7")));
+    assert!(score > 200);
+}